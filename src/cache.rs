@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::desktop::DesktopEntry;
+
+/// `binaries.cache` holds one name per line; `binaries.dirs` records what
+/// it was built from: the scan mode, then `<mtime>\t<dir>` for each directory.
+const NAMES_FILE: &str = "binaries.cache";
+const DIRS_FILE: &str = "binaries.dirs";
+/// `desktop.cache` holds the parsed desktop entries as TOML, with the stamp
+/// (see `desktop::scan`) of the files they were parsed from.
+const DESKTOP_FILE: &str = "desktop.cache";
+
+#[derive(Serialize, Deserialize)]
+struct DesktopCache {
+    stamp: String,
+    entries: Vec<DesktopEntry>,
+}
+
+/// `$XDG_CACHE_HOME/deemenu`, or `~/.cache/deemenu`
+fn cache_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CACHE_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join("deemenu"))
+}
+
+/// Cached binary names, if the same directories were scanned the same way
+/// and none of them has changed since.
+pub fn load(dirs: &[String], strict_exec: bool) -> Option<Vec<String>> {
+    load_from(&cache_dir()?, dirs, strict_exec)
+}
+
+/// Saves a fresh scan of `dirs`. Failing to write just means rescanning next time.
+pub fn store(dirs: &[String], strict_exec: bool, names: &[String]) {
+    let Some(dir) = cache_dir() else { return };
+    if let Err(e) = store_in(&dir, dirs, strict_exec, names) {
+        eprintln!("deemenu: warning: can't write cache in {}: {}", dir.display(), e);
+    }
+}
+
+/// Cached desktop entries, if they were parsed from files matching `stamp`.
+pub fn load_desktop(stamp: &str) -> Option<Vec<DesktopEntry>> {
+    load_desktop_from(&cache_dir()?, stamp)
+}
+
+/// Saves freshly parsed desktop entries. Failing to write just means parsing next time.
+pub fn store_desktop(stamp: &str, entries: &[DesktopEntry]) {
+    let Some(dir) = cache_dir() else { return };
+    if let Err(e) = store_desktop_in(&dir, stamp, entries) {
+        eprintln!("deemenu: warning: can't write cache in {}: {}", dir.display(), e);
+    }
+}
+
+fn load_desktop_from(cache: &Path, stamp: &str) -> Option<Vec<DesktopEntry>> {
+    let contents = fs::read_to_string(cache.join(DESKTOP_FILE)).ok()?;
+    let cached: DesktopCache = toml::from_str(&contents).ok()?;
+    (cached.stamp == stamp).then_some(cached.entries)
+}
+
+fn store_desktop_in(cache: &Path, stamp: &str, entries: &[DesktopEntry]) -> std::io::Result<()> {
+    let cached = DesktopCache { stamp: stamp.to_string(), entries: entries.to_vec() };
+    let contents = toml::to_string(&cached).map_err(std::io::Error::other)?;
+    fs::create_dir_all(cache)?;
+    write_atomically(&cache.join(DESKTOP_FILE), &contents)
+}
+
+fn load_from(cache: &Path, dirs: &[String], strict_exec: bool) -> Option<Vec<String>> {
+    let recorded = fs::read_to_string(cache.join(DIRS_FILE)).ok()?;
+    if recorded != describe(dirs, strict_exec) {
+        return None;
+    }
+
+    let names = fs::read_to_string(cache.join(NAMES_FILE)).ok()?;
+    Some(names.lines().map(str::to_string).collect())
+}
+
+fn store_in(cache: &Path, dirs: &[String], strict_exec: bool, names: &[String]) -> std::io::Result<()> {
+    fs::create_dir_all(cache)?;
+
+    // Names first: a crash in between leaves a sidecar that no longer matches
+    write_atomically(&cache.join(NAMES_FILE), &names.join("\n"))?;
+    write_atomically(&cache.join(DIRS_FILE), &describe(dirs, strict_exec))
+}
+
+/// Replaces `path` with `contents` in one rename, so readers never see half a file.
+pub fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    // Per file and process, so concurrent writers never share a temp file
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!("{}.{}.tmp", name, std::process::id()));
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, path)
+}
+
+/// The sidecar contents for the directories as they are right now.
+/// Missing directories are recorded too, so one appearing invalidates the cache.
+fn describe(dirs: &[String], strict_exec: bool) -> String {
+    let mut out = format!("strict_exec={}\n", strict_exec);
+    for dir in dirs {
+        out.push_str(&format!("{}\t{}\n", mtime(Path::new(dir)), dir));
+    }
+    out
+}
+
+/// When `path` was last modified, to the nanosecond; `-` if it doesn't exist.
+pub fn mtime(path: &Path) -> String {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or("-".to_string(), |age| format!("{}.{:09}", age.as_secs(), age.subsec_nanos()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalidates_when_a_directory_changes() {
+        let root = env::temp_dir().join(format!("deemenu-cache-test-{}", std::process::id()));
+        let bin = root.join("bin");
+        let cache = root.join("cache");
+        fs::create_dir_all(&bin).unwrap();
+        let dirs = [bin.to_string_lossy().to_string()];
+        let names = ["htop".to_string(), "vim".to_string()];
+
+        store_in(&cache, &dirs, false, &names).unwrap();
+        assert_eq!(load_from(&cache, &dirs, false).as_deref(), Some(&names[..]));
+        assert_eq!(load_from(&cache, &dirs, true), None);
+
+        // Adding a file bumps the directory mtime; sleep so coarse timestamps still differ
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(bin.join("new-tool"), "").unwrap();
+        assert_eq!(load_from(&cache, &dirs, false), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn desktop_entries_come_back_for_the_same_stamp() {
+        let cache = env::temp_dir().join(format!("deemenu-desktop-cache-test-{}", std::process::id()));
+        let entry = DesktopEntry {
+            name: "Firefox".to_string(),
+            exec: "firefox %u".to_string(),
+            categories: vec!["Network".to_string()],
+            icon: Some("firefox".to_string()),
+            ..Default::default()
+        };
+
+        store_desktop_in(&cache, "stamp 1", &[entry]).unwrap();
+        let loaded = load_desktop_from(&cache, "stamp 1").unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!((loaded[0].name.as_str(), loaded[0].exec.as_str()), ("Firefox", "firefox %u"));
+        assert_eq!((loaded[0].categories.as_slice(), loaded[0].icon.as_deref()), (&["Network".to_string()][..], Some("firefox")));
+        assert!(load_desktop_from(&cache, "stamp 2").is_none());
+
+        fs::remove_dir_all(cache).unwrap();
+    }
+}
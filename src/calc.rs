@@ -0,0 +1,210 @@
+//! Tiny arithmetic evaluator behind the `=` prefix, with unit conversions
+//! like `10 mi to km`.
+//!
+//! Grammar (usual precedence, left associative):
+//!   expr   := term (('+' | '-') term)*
+//!   term   := factor (('*' | '/' | '%') factor)*
+//!   factor := ('+' | '-') factor | number | '(' expr ')'
+
+#[derive(Clone, Copy, PartialEq)]
+enum Quantity {
+    Length,
+    Mass,
+    Temperature,
+    Data,
+}
+
+/// Names of a unit, what it measures, and `base = value * scale + offset`
+/// (base units: meter, kilogram, kelvin, byte).
+const UNITS: &[(&[&str], Quantity, f64, f64)] = &[
+    (&["mm", "millimeter", "millimeters"], Quantity::Length, 0.001, 0.0),
+    (&["cm", "centimeter", "centimeters"], Quantity::Length, 0.01, 0.0),
+    (&["m", "meter", "meters", "metre", "metres"], Quantity::Length, 1.0, 0.0),
+    (&["km", "kilometer", "kilometers", "kilometre", "kilometres"], Quantity::Length, 1000.0, 0.0),
+    (&["in", "inch", "inches"], Quantity::Length, 0.0254, 0.0),
+    (&["ft", "foot", "feet"], Quantity::Length, 0.3048, 0.0),
+    (&["yd", "yard", "yards"], Quantity::Length, 0.9144, 0.0),
+    (&["mi", "mile", "miles"], Quantity::Length, 1609.344, 0.0),
+    (&["nmi"], Quantity::Length, 1852.0, 0.0),
+    (&["mg", "milligram", "milligrams"], Quantity::Mass, 1e-6, 0.0),
+    (&["g", "gram", "grams"], Quantity::Mass, 0.001, 0.0),
+    (&["kg", "kilogram", "kilograms", "kilo", "kilos"], Quantity::Mass, 1.0, 0.0),
+    (&["t", "tonne", "tonnes"], Quantity::Mass, 1000.0, 0.0),
+    (&["oz", "ounce", "ounces"], Quantity::Mass, 0.028349523125, 0.0),
+    (&["lb", "lbs", "pound", "pounds"], Quantity::Mass, 0.45359237, 0.0),
+    (&["st", "stone"], Quantity::Mass, 6.35029318, 0.0),
+    (&["c", "°c", "celsius"], Quantity::Temperature, 1.0, 273.15),
+    (&["f", "°f", "fahrenheit"], Quantity::Temperature, 5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0),
+    (&["k", "kelvin"], Quantity::Temperature, 1.0, 0.0),
+    (&["bit", "bits"], Quantity::Data, 0.125, 0.0),
+    (&["b", "byte", "bytes"], Quantity::Data, 1.0, 0.0),
+    (&["kb"], Quantity::Data, 1e3, 0.0),
+    (&["mb"], Quantity::Data, 1e6, 0.0),
+    (&["gb"], Quantity::Data, 1e9, 0.0),
+    (&["tb"], Quantity::Data, 1e12, 0.0),
+    (&["kib"], Quantity::Data, 1024.0, 0.0),
+    (&["mib"], Quantity::Data, 1024.0 * 1024.0, 0.0),
+    (&["gib"], Quantity::Data, 1024.0 * 1024.0 * 1024.0, 0.0),
+    (&["tib"], Quantity::Data, 1024.0 * 1024.0 * 1024.0 * 1024.0, 0.0),
+];
+
+/// What the `=` prefix shows: a conversion with its target unit (`16.09344 km`),
+/// or the value of an arithmetic expression.
+pub fn answer(query: &str) -> Option<String> {
+    match convert(query) {
+        Some((value, unit)) => Some(format!("{} {}", format(value), unit)),
+        None => evaluate(query).map(format),
+    }
+}
+
+/// `10 mi to km`, `(2+3)kg in lb`, `100f to c`: the expression's value in the
+/// target unit (rounded to 6 decimals), and that unit as typed.
+fn convert(query: &str) -> Option<(f64, String)> {
+    let query = query.trim().to_lowercase();
+    let (from, to) = [" to ", " in "].iter().find_map(|keyword| {
+        let at = query.rfind(keyword)?;
+        Some((query[..at].trim(), query[at + keyword.len()..].trim()))
+    })?;
+
+    let unit_start = from.rfind(|c: char| !c.is_alphabetic() && c != '°').map_or(0, |i| i + 1);
+    let (expr, from_unit) = from.split_at(unit_start);
+    let (from_quantity, from_scale, from_offset) = unit(from_unit)?;
+    let (to_quantity, to_scale, to_offset) = unit(to)?;
+    if from_quantity != to_quantity {
+        return None;
+    }
+
+    let base = evaluate(expr)? * from_scale + from_offset;
+    let value = ((base - to_offset) / to_scale * 1e6).round() / 1e6;
+    value.is_finite().then(|| (value, to.to_string()))
+}
+
+fn unit(name: &str) -> Option<(Quantity, f64, f64)> {
+    UNITS.iter()
+        .find(|(names, ..)| names.contains(&name))
+        .map(|&(_, quantity, scale, offset)| (quantity, scale, offset))
+}
+
+/// Evaluates an arithmetic expression, returning `None` if it is malformed
+/// or doesn't produce a finite number (e.g. division by zero).
+pub fn evaluate(expr: &str) -> Option<f64> {
+    let mut parser = Parser {
+        chars: expr.chars().filter(|c| !c.is_whitespace()).collect(),
+        pos: 0,
+    };
+
+    let value = parser.expr()?;
+    if parser.pos != parser.chars.len() { return None; }
+
+    value.is_finite().then_some(value)
+}
+
+/// Formats a result without a trailing `.0` for whole numbers.
+pub fn format(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expr(&mut self) -> Option<f64> {
+        let mut value = self.term()?;
+        while let Some(op) = self.peek() {
+            match op {
+                '+' => { self.pos += 1; value += self.term()?; }
+                '-' => { self.pos += 1; value -= self.term()?; }
+                _ => break,
+            }
+        }
+        Some(value)
+    }
+
+    fn term(&mut self) -> Option<f64> {
+        let mut value = self.factor()?;
+        while let Some(op) = self.peek() {
+            match op {
+                '*' => { self.pos += 1; value *= self.factor()?; }
+                '/' => { self.pos += 1; value /= self.factor()?; }
+                '%' => { self.pos += 1; value %= self.factor()?; }
+                _ => break,
+            }
+        }
+        Some(value)
+    }
+
+    fn factor(&mut self) -> Option<f64> {
+        match self.peek()? {
+            '+' => { self.pos += 1; self.factor() }
+            '-' => { self.pos += 1; self.factor().map(|v| -v) }
+            '(' => {
+                self.pos += 1;
+                let value = self.expr()?;
+                if self.peek()? != ')' { return None; }
+                self.pos += 1;
+                Some(value)
+            }
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Option<f64> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect::<String>().parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn respects_precedence_and_parentheses() {
+        assert_eq!(evaluate("3*(4+5)"), Some(27.0));
+        assert_eq!(evaluate("2+3*4"), Some(14.0));
+        assert_eq!(evaluate("10 % 4 - -1"), Some(3.0));
+        assert_eq!(evaluate("1.5/2"), Some(0.75));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert_eq!(evaluate("2+"), None);
+        assert_eq!(evaluate("(1+2"), None);
+        assert_eq!(evaluate("1/0"), None);
+        assert_eq!(evaluate("abc"), None);
+    }
+
+    #[test]
+    fn converts_between_units_of_the_same_quantity() {
+        assert_eq!(answer("10 mi to km").as_deref(), Some("16.09344 km"));
+        assert_eq!(answer("100f to c").as_deref(), Some("37.777778 c"));
+        assert_eq!(answer("-40 °C in F").as_deref(), Some("-40 f"));
+        assert_eq!(answer("12 in in cm").as_deref(), Some("30.48 cm"));
+        assert_eq!(answer("(1+1) GiB to MB").as_deref(), Some("2147.483648 mb"));
+        assert_eq!(answer("2 kg to lb").as_deref(), Some("4.409245 lb"));
+        assert_eq!(answer("1+2").as_deref(), Some("3"));
+
+        assert_eq!(answer("10 km to kg"), None);
+        assert_eq!(answer("10 parsecs to km"), None);
+        assert_eq!(answer("km to mi"), None);
+    }
+
+    #[test]
+    fn formats_whole_numbers_without_decimals() {
+        assert_eq!(format(27.0), "27");
+        assert_eq!(format(0.75), "0.75");
+    }
+}
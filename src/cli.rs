@@ -0,0 +1,277 @@
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use crate::completions;
+
+/// A command line option, for the help text, shell completions and man page.
+pub struct Opt {
+    pub short: Option<char>,
+    pub long: &'static str,
+    /// Name of the value it takes, if any
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+pub const OPTIONS: &[Opt] = &[
+    Opt { short: None, long: "config", value: Some("path"), help: "Read config from <path> instead of ~/.config/deemenu/config.toml" },
+    Opt { short: None, long: "strict-exec", value: None, help: "Only list files that have an execute bit" },
+    Opt { short: None, long: "max-results", value: Some("n"), help: "Show at most <n> results at once" },
+    Opt { short: None, long: "no-cache", value: None, help: "Rescan PATH instead of using the cached list" },
+    Opt { short: None, long: "dry-run", value: None, help: "Print the commands that would run instead of running them" },
+    Opt { short: None, long: "dmenu", value: None, help: "Choose from the lines on stdin and print the choice to stdout" },
+    Opt { short: Some('d'), long: "delimiter", value: Some("text"), help: "With --dmenu, split lines into fields at <text> instead of whitespace" },
+    Opt { short: None, long: "with-nth", value: Some("n"), help: "With --dmenu, list only field <n> of each line" },
+    Opt { short: None, long: "accept-nth", value: Some("n"), help: "With --dmenu, print field <n> of the chosen line instead of all of it" },
+    Opt { short: None, long: "print-command", value: None, help: "Print the chosen command line to stdout instead of running it" },
+    Opt { short: None, long: "last", value: None, help: "Run the most recently launched entry again without opening a window" },
+    Opt { short: None, long: "recent", value: None, help: "Open with only recently launched entries listed until something is typed" },
+    Opt { short: None, long: "stats", value: None, help: "Print launch counts and last-used times (name, count, unix time) and exit" },
+    Opt { short: Some('p'), long: "prompt", value: Some("text"), help: "Show <text> before the search box" },
+    Opt { short: Some('h'), long: "help", value: None, help: "Print this help and exit" },
+    Opt { short: Some('V'), long: "version", value: None, help: "Print the version and exit" },
+];
+
+/// `--help`: every option in `OPTIONS`. `--generate-completions <shell>` and
+/// `--generate-man` are for packagers and left out.
+fn usage() -> String {
+    let flags: Vec<String> = OPTIONS.iter().map(|opt| {
+        let mut flag = match opt.short {
+            Some(short) => format!("-{}, --{}", short, opt.long),
+            None => format!("--{}", opt.long),
+        };
+        if let Some(value) = opt.value {
+            flag.push_str(&format!(" <{}>", value));
+        }
+        flag
+    }).collect();
+
+    // Help texts line up after the longest flag
+    let width = flags.iter().map(String::len).max().unwrap_or_default();
+    let mut usage = "Usage: deemenu [OPTIONS]\n\nOptions:".to_string();
+    for (flag, opt) in flags.iter().zip(OPTIONS) {
+        usage.push_str(&format!("\n  {:<width$}  {}", flag, opt.help, width = width));
+    }
+    usage
+}
+
+/// Options given on the command line. These override the config file.
+#[derive(Default, Debug, PartialEq)]
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub strict_exec: bool,
+    pub max_results: Option<usize>,
+    pub no_cache: bool,
+    pub dry_run: bool,
+    pub dmenu: bool,
+    /// `--dmenu` field options, see `core::Fields`
+    pub delimiter: Option<String>,
+    pub with_nth: Option<usize>,
+    pub accept_nth: Option<usize>,
+    pub print_command: bool,
+    pub last: bool,
+    pub recent: bool,
+    pub stats: bool,
+    pub prompt: Option<String>,
+}
+
+enum Action {
+    Run(Args),
+    Help,
+    Version,
+    Completions(String),
+    Man,
+}
+
+/// Parses `std::env::args`, handling `--help`/`--version` and bad input by exiting.
+pub fn parse() -> Args {
+    match parse_from(env::args().skip(1)) {
+        Ok(Action::Run(args)) => args,
+        Ok(Action::Help) => {
+            println!("{}", usage());
+            process::exit(0);
+        }
+        Ok(Action::Completions(shell)) => {
+            print!("{}", completions::script(&shell).unwrap_or_default());
+            process::exit(0);
+        }
+        Ok(Action::Man) => {
+            print!("{}", completions::man_page());
+            process::exit(0);
+        }
+        Ok(Action::Version) => {
+            println!("deemenu {}", env!("CARGO_PKG_VERSION"));
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("deemenu: {}\n\n{}", e, usage());
+            process::exit(2);
+        }
+    }
+}
+
+fn parse_from(mut argv: impl Iterator<Item = String>) -> Result<Action, String> {
+    let mut args = Args::default();
+
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "-V" | "--version" => return Ok(Action::Version),
+            "--generate-man" => return Ok(Action::Man),
+            "--generate-completions" => {
+                let shell = argv.next().ok_or("--generate-completions needs a shell: bash, zsh or fish")?;
+                if completions::script(&shell).is_none() {
+                    return Err(format!("can't generate completions for '{}', only bash, zsh or fish", shell));
+                }
+                return Ok(Action::Completions(shell));
+            }
+            "--strict-exec" => args.strict_exec = true,
+            "--no-cache" => args.no_cache = true,
+            "--dry-run" => args.dry_run = true,
+            "--dmenu" => args.dmenu = true,
+            "--print-command" => args.print_command = true,
+            "--last" => args.last = true,
+            "--recent" => args.recent = true,
+            "--stats" => args.stats = true,
+            "--config" => {
+                let path = argv.next().ok_or("--config needs a path")?;
+                args.config = Some(PathBuf::from(path));
+            }
+            "-p" | "--prompt" => {
+                args.prompt = Some(argv.next().ok_or("--prompt needs some text")?);
+            }
+            "--max-results" => {
+                let value = argv.next().ok_or("--max-results needs a number")?;
+                args.max_results = Some(parse_max_results(&value)?);
+            }
+            "-d" | "--delimiter" => {
+                args.delimiter = Some(argv.next().ok_or("--delimiter needs some text")?);
+            }
+            "--with-nth" | "--accept-nth" => {
+                let value = argv.next().ok_or(format!("{} needs a field number", arg))?;
+                let field = Some(parse_field(&arg, &value)?);
+                if arg == "--with-nth" { args.with_nth = field } else { args.accept_nth = field }
+            }
+            other => {
+                if let Some(path) = other.strip_prefix("--config=") {
+                    args.config = Some(PathBuf::from(path));
+                } else if let Some(text) = other.strip_prefix("--prompt=") {
+                    args.prompt = Some(text.to_string());
+                } else if let Some(value) = other.strip_prefix("--max-results=") {
+                    args.max_results = Some(parse_max_results(value)?);
+                } else if let Some(text) = other.strip_prefix("--delimiter=") {
+                    args.delimiter = Some(text.to_string());
+                } else if let Some(value) = other.strip_prefix("--with-nth=") {
+                    args.with_nth = Some(parse_field("--with-nth", value)?);
+                } else if let Some(value) = other.strip_prefix("--accept-nth=") {
+                    args.accept_nth = Some(parse_field("--accept-nth", value)?);
+                } else {
+                    return Err(format!("unknown option '{}'", other));
+                }
+            }
+        }
+    }
+
+    // `--last` runs the last launch without any menu, so there is nothing to choose from stdin
+    if args.last && args.dmenu {
+        return Err("--last can't be combined with --dmenu".to_string());
+    }
+
+    Ok(Action::Run(args))
+}
+
+fn parse_max_results(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("--max-results needs a positive number, got '{}'", value)),
+    }
+}
+
+fn parse_field(flag: &str, value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{} needs a field number from 1, got '{}'", flag, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(argv: &[&str]) -> Result<Args, String> {
+        match parse_from(argv.iter().map(|a| a.to_string()))? {
+            Action::Run(args) => Ok(args),
+            Action::Help => Err("help".to_string()),
+            Action::Version => Err("version".to_string()),
+            Action::Completions(shell) => Err(format!("completions for {}", shell)),
+            Action::Man => Err("man".to_string()),
+        }
+    }
+
+    #[test]
+    fn parses_config_path_and_flags() {
+        let args = parse_args(&["--strict-exec", "--config", "/tmp/deemenu.toml", "--dry-run"]).unwrap();
+        assert_eq!(args, Args {
+            config: Some("/tmp/deemenu.toml".into()),
+            strict_exec: true,
+            max_results: None,
+            no_cache: false,
+            dry_run: true,
+            dmenu: false,
+            delimiter: None,
+            with_nth: None,
+            accept_nth: None,
+            print_command: false,
+            last: false,
+            recent: false,
+            stats: false,
+            prompt: None,
+        });
+
+        assert!(parse_args(&["--no-cache"]).unwrap().no_cache);
+        assert!(parse_args(&["--dmenu"]).unwrap().dmenu);
+        assert!(parse_args(&["--recent"]).unwrap().recent);
+        assert!(parse_args(&["--last"]).unwrap().last);
+        assert!(parse_args(&["--print-command"]).unwrap().print_command);
+        assert!(parse_args(&["--stats"]).unwrap().stats);
+        assert_eq!(parse_args(&["-p", "Pick file:"]).unwrap().prompt.as_deref(), Some("Pick file:"));
+        assert_eq!(parse_args(&["--max-results", "5"]).unwrap().max_results, Some(5));
+        assert_eq!(parse_args(&["--max-results=1"]).unwrap().max_results, Some(1));
+
+        let args = parse_args(&["--dmenu", "-d", ",", "--with-nth", "2", "--accept-nth=1"]).unwrap();
+        assert_eq!((args.delimiter.as_deref(), args.with_nth, args.accept_nth), (Some(","), Some(2), Some(1)));
+
+        let args = parse_args(&["--config=/tmp/other.toml"]).unwrap();
+        assert_eq!(args.config, Some("/tmp/other.toml".into()));
+    }
+
+    #[test]
+    fn help_and_version_short_circuit() {
+        assert_eq!(parse_args(&["--help", "--bogus"]), Err("help".to_string()));
+        assert_eq!(parse_args(&["-V"]), Err("version".to_string()));
+        assert_eq!(parse_args(&["--generate-completions", "zsh"]), Err("completions for zsh".to_string()));
+        assert_eq!(parse_args(&["--generate-man"]), Err("man".to_string()));
+    }
+
+    #[test]
+    fn usage_lists_every_option_aligned() {
+        let usage = usage();
+        assert!(usage.starts_with("Usage: deemenu [OPTIONS]\n\nOptions:\n  --config <path>         Read config"));
+        assert!(usage.contains("\n  -d, --delimiter <text>  With --dmenu"));
+        assert!(usage.contains("\n  -p, --prompt <text>     Show <text> before the search box\n"));
+        assert!(!usage.contains("generate"));
+    }
+
+    #[test]
+    fn rejects_unknown_flags_and_missing_values() {
+        assert!(parse_args(&["--bogus"]).is_err());
+        assert!(parse_args(&["--config"]).is_err());
+        assert!(parse_args(&["-p"]).is_err());
+        assert!(parse_args(&["--max-results", "0"]).is_err());
+        assert!(parse_args(&["--max-results=lots"]).is_err());
+        assert!(parse_args(&["--with-nth", "0"]).is_err());
+        assert!(parse_args(&["-d"]).is_err());
+        assert!(parse_args(&["--last", "--dmenu"]).is_err());
+        assert!(parse_args(&["--generate-completions", "tcsh"]).is_err());
+    }
+}
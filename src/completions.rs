@@ -0,0 +1,167 @@
+//! `--generate-completions <shell>` and `--generate-man`: shell completion
+//! scripts and a man page for the options in `cli::OPTIONS`, for packagers.
+
+use crate::cli::{Opt, OPTIONS};
+
+/// The completion script for `shell` ("bash", "zsh" or "fish").
+pub fn script(shell: &str) -> Option<String> {
+    match shell {
+        "bash" => Some(bash()),
+        "zsh" => Some(zsh()),
+        "fish" => Some(fish()),
+        _ => None,
+    }
+}
+
+fn flags(opt: &Opt) -> Vec<String> {
+    opt.short.map(|short| format!("-{}", short)).into_iter().chain([format!("--{}", opt.long)]).collect()
+}
+
+fn bash() -> String {
+    let all: Vec<String> = OPTIONS.iter().flat_map(flags).collect();
+    let valued: Vec<String> = OPTIONS.iter().filter(|opt| opt.value.is_some() && opt.long != "config").flat_map(flags).collect();
+
+    format!(
+        r#"_deemenu() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    case "$prev" in
+        --config) COMPREPLY=($(compgen -f -- "$cur")); return ;;
+        {}) return ;;
+        --generate-completions) COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur")); return ;;
+    esac
+    COMPREPLY=($(compgen -W "{}" -- "$cur"))
+}}
+complete -F _deemenu deemenu
+"#,
+        valued.join("|"),
+        all.join(" "),
+    )
+}
+
+fn zsh() -> String {
+    let mut script = "#compdef deemenu\n\n_arguments \\\n".to_string();
+    for opt in OPTIONS {
+        let help = opt.help.replace('\'', r"'\''").replace('[', r"\[").replace(']', r"\]");
+        let value = match (opt.long, opt.value) {
+            ("config", Some(value)) => format!(":{}:_files", value),
+            (_, Some(value)) => format!(":{}:", value),
+            (_, None) => String::new(),
+        };
+        let spec = match opt.short {
+            Some(short) => format!("'(-{0} --{1})'{{-{0},--{1}}}'[{2}]{3}'", short, opt.long, help, value),
+            None => format!("'--{}[{}]{}'", opt.long, help, value),
+        };
+        script.push_str(&format!("  {} \\\n", spec));
+    }
+    script.push_str("  '--generate-completions[Print a shell completion script]:shell:(bash zsh fish)' \\\n");
+    script.push_str("  '--generate-man[Print the man page]'\n");
+    script
+}
+
+fn fish() -> String {
+    let mut script = String::new();
+    for opt in OPTIONS {
+        let mut line = "complete -c deemenu".to_string();
+        if let Some(short) = opt.short {
+            line.push_str(&format!(" -s {}", short));
+        }
+        line.push_str(&format!(" -l {}", opt.long));
+        match (opt.long, opt.value) {
+            ("config", Some(_)) => line.push_str(" -r -F"),
+            (_, Some(_)) => line.push_str(" -x"),
+            (_, None) => {}
+        }
+        line.push_str(&format!(" -d '{}'\n", opt.help.replace('\\', r"\\").replace('\'', r"\'")));
+        script.push_str(&line);
+    }
+    script.push_str("complete -c deemenu -l generate-completions -x -a 'bash zsh fish' -d 'Print a shell completion script'\n");
+    script.push_str("complete -c deemenu -l generate-man -d 'Print the man page'\n");
+    script
+}
+
+/// deemenu(1) in roff, without a date so the output stays the same between builds.
+pub fn man_page() -> String {
+    let mut page = format!(
+        ".TH DEEMENU 1 \"\" \"deemenu {}\" \"User Commands\"\n\
+         .SH NAME\n\
+         deemenu \\- a dmenu-style application launcher\n\
+         .SH SYNOPSIS\n\
+         .B deemenu\n\
+         [\\fIOPTIONS\\fR]\n\
+         .SH DESCRIPTION\n\
+         Lists the programs on PATH and the installed desktop entries, filters them as you type, and runs the highlighted one on Enter.\n\
+         .SH OPTIONS\n",
+        env!("CARGO_PKG_VERSION"),
+    );
+    for opt in OPTIONS {
+        let flags: Vec<String> = flags(opt).iter().map(|flag| format!("\\fB{}\\fR", roff(flag))).collect();
+        let value = opt.value.map(|value| format!(" \\fI{}\\fR", value)).unwrap_or_default();
+        let mut help = roff(opt.help);
+        if let Some(value) = opt.value {
+            help = help.replace(&format!("<{}>", value), &format!("\\fI{}\\fR", value));
+        }
+        page.push_str(&format!(".TP\n{}{}\n{}\n", flags.join(", "), value, help));
+    }
+    page.push_str(
+        ".SH FILES\n\
+         .TP\n\
+         \\fI~/.config/deemenu/config.toml\\fR\n\
+         Settings; see the README for every key.\n\
+         .TP\n\
+         \\fI~/.local/state/deemenu/history\\fR\n\
+         Launch counts, printed by \\fB\\-\\-stats\\fR.\n\
+         .SH EXIT STATUS\n\
+         .TP\n\
+         0\n\
+         Something was launched, copied or printed.\n\
+         .TP\n\
+         1\n\
+         Nothing was launched: the menu was closed or cancelled, or the launch failed.\n\
+         .TP\n\
+         2\n\
+         Bad command line options.\n\
+         .TP\n\
+         3\n\
+         No display server was found.\n",
+    );
+    page
+}
+
+/// `text` with roff's special characters escaped.
+fn roff(text: &str) -> String {
+    let text = text.replace('\\', r"\e").replace('-', r"\-");
+    match text.starts_with(['.', '\'']) {
+        true => format!("\\&{}", text),
+        false => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_cover_every_option() {
+        for shell in ["bash", "zsh", "fish"] {
+            let script = script(shell).unwrap();
+            for opt in OPTIONS {
+                assert!(script.contains(opt.long), "{} misses --{}", shell, opt.long);
+            }
+            assert_eq!(script, super::script(shell).unwrap());
+        }
+        assert!(script("tcsh").is_none());
+
+        assert!(bash().contains("--max-results|-d|--delimiter|--with-nth|--accept-nth|-p|--prompt) return ;;"));
+        assert!(zsh().contains(r"'(-p --prompt)'{-p,--prompt}'[Show <text> before the search box]:text:'"));
+        assert!(fish().contains("complete -c deemenu -l config -r -F -d 'Read config from <path> instead of ~/.config/deemenu/config.toml'\n"));
+    }
+
+    #[test]
+    fn man_page_escapes_dashes_and_marks_values() {
+        let page = man_page();
+        assert!(page.starts_with(".TH DEEMENU 1"));
+        assert!(page.contains(".TP\n\\fB\\-p\\fR, \\fB\\-\\-prompt\\fR \\fItext\\fR\nShow \\fItext\\fR before the search box\n"));
+        assert!(page.contains("\\fB\\-\\-dry\\-run\\fR\n"));
+        assert!(page.contains(".TP\n0\nSomething was launched, copied or printed.\n.TP\n1\n"));
+    }
+}
@@ -0,0 +1,442 @@
+use eframe::egui;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::keys::Keys;
+
+/// How results are arranged: a dmenu-style strip or a rofi-style centered list
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    Horizontal,
+    Vertical,
+}
+
+/// How search text is compared to names
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseMode {
+    /// Case-insensitive unless the query contains an uppercase letter
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+/// What Enter runs when the query has no prefix
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnterPolicy {
+    /// The highlighted entry, unless arguments were typed after a name
+    Auto,
+    /// Always the highlighted entry; the typed text only when nothing matches
+    Selection,
+    /// The typed text when its first word is a program's exact name, otherwise the highlighted entry
+    Exact,
+    /// Always the typed text, as a command line
+    Raw,
+}
+
+/// Where launch failures are reported besides the window
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Notify {
+    /// Desktop notification through the `notify-send` command
+    NotifySend,
+    None,
+}
+
+/// The `_NET_WM_WINDOW_TYPE` asked for on X11
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowType {
+    Normal,
+    /// Kept out of taskbars and Alt+Tab, like a panel
+    Dock,
+}
+
+/// How programs are started
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Launcher {
+    /// As our own children, in their own process group
+    Direct,
+    /// Through `systemd-run --user --scope`, each in a transient scope of its own
+    SystemdRun,
+}
+
+impl Launcher {
+    /// What goes before the command line to launch it this way.
+    pub fn prefix(self) -> &'static [&'static str] {
+        match self {
+            Launcher::Direct => &[],
+            Launcher::SystemdRun => &["systemd-run", "--user", "--scope", "--quiet", "--"],
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(try_from = "MonitorValue")]
+pub enum MonitorChoice {
+    Primary,
+    Cursor,
+    Index(usize),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MonitorValue {
+    Index(usize),
+    Name(String),
+}
+
+impl TryFrom<MonitorValue> for MonitorChoice {
+    type Error = String;
+
+    fn try_from(value: MonitorValue) -> Result<Self, Self::Error> {
+        match value {
+            MonitorValue::Index(i) => Ok(MonitorChoice::Index(i)),
+            MonitorValue::Name(name) => match name.as_str() {
+                "primary" => Ok(MonitorChoice::Primary),
+                "cursor" => Ok(MonitorChoice::Cursor),
+                other => Err(format!("unknown monitor \"{}\", expected \"primary\", \"cursor\" or an index", other)),
+            },
+        }
+    }
+}
+
+/// Commands to ask about before running them, in the `[confirm]` table
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Confirm {
+    /// Pieces of command lines that need confirming, e.g. `"rm -rf"`; case-insensitive
+    pub patterns: Vec<String>,
+    /// Confirm every sudo command, matching or not
+    pub sudo: bool,
+}
+
+impl Confirm {
+    /// Whether `command` (to be run with sudo if `sudo`) must be confirmed first.
+    pub fn wants(&self, command: &str, sudo: bool) -> bool {
+        let command = command.to_lowercase();
+        (sudo && self.sudo)
+            || self.patterns.iter().any(|pattern| !pattern.is_empty() && command.contains(&pattern.to_lowercase()))
+    }
+}
+
+/// User settings read from `~/.config/deemenu/config.toml`.
+/// Every key is optional; anything missing keeps the built-in default.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub layout: Layout,
+    /// Window width; the monitor's width for the horizontal bar, 600 for the vertical box
+    pub width: Option<f32>,
+    pub height: f32,
+    /// Most result rows the vertical layout grows to
+    pub lines: usize,
+    /// Most results shown at once; moving the highlight past them shows the next ones
+    pub max_results: usize,
+    /// Longer result names are cut short with an ellipsis (hover shows them whole); 0 for no limit
+    pub max_name_length: usize,
+    /// How many results PageUp/PageDown move the highlight
+    pub page_size: usize,
+    /// Which monitor to open on: "primary", "cursor" or a monitor index
+    pub monitor: MonitorChoice,
+    /// Offset of the window from the monitor's top-left corner.
+    /// Unset means top-left for the bar and centered for the vertical box.
+    pub position: Option<[f32; 2]>,
+    /// On Wayland, run through XWayland so `position`/`monitor` placement works
+    pub xwayland: bool,
+    /// X11 window type: "normal", or "dock" to stay out of taskbars and Alt+Tab
+    pub window_type: WindowType,
+    pub accent_color: [u8; 3],
+    pub panel_color: [u8; 3],
+    pub text_color: [u8; 3],
+    /// Drawn between the search box and the results; empty for none
+    pub separator: String,
+    pub separator_color: [u8; 3],
+    /// How round the highlighted result's corners are
+    pub corner_radius: f32,
+    /// Fade the highlighted result toward the panel color while the window isn't focused
+    pub dim_unfocused: bool,
+    /// Space a result adds around its name, horizontally (half on each side) and vertically
+    pub padding: [f32; 2],
+    pub font_size: f32,
+    /// TTF/OTF file used instead of the built-in font; `~` is expanded
+    pub font: Option<String>,
+    /// Name to register `font` under; defaults to the file name without extension
+    pub font_name: Option<String>,
+    /// Shown before the search box, like dmenu's prompt (e.g. `"❯"`); empty for none
+    pub prompt: String,
+    /// Placeholder in the empty search box
+    pub hint_text: String,
+    /// Background opacity from 0.0 to 1.0; text always stays opaque
+    pub opacity: f32,
+    /// Use a transparent window even at full opacity, e.g. for compositor blur rules
+    pub transparent: bool,
+    /// Terminal launcher prefix, e.g. `"kitty -e"`. Autodetected when unset.
+    pub terminal: Option<String>,
+    /// How programs are started: "direct" or "systemd-run" (not sudo commands)
+    pub launcher: Launcher,
+    /// Only list files with an execute bit instead of every non-directory in PATH
+    pub strict_exec: bool,
+    /// Keep where each binary was found: run it by that path, and list binaries
+    /// shadowed by an earlier PATH entry too, as `name (dir)`
+    pub full_paths: bool,
+    /// Reuse the last PATH scan and desktop entries from `~/.cache/deemenu` while their files are unchanged
+    pub cache: bool,
+    /// Count launches in `~/.local/state/deemenu/history`, for `--stats`, and
+    /// keep command lines run with arguments in `commands` to suggest again
+    pub history: bool,
+    /// Directories to scan besides PATH and the standard ones; `~` is expanded
+    pub extra_paths: Vec<String>,
+    /// Directories never to scan, even when on PATH
+    pub exclude_paths: Vec<String>,
+    /// Names never listed, e.g. `"gsd-*"` (`*` matches anything); desktop
+    /// entries are also matched by the program they run
+    pub blocklist: Vec<String>,
+    /// When set, only matching names are listed, and only what is listed can be run
+    pub allowlist: Vec<String>,
+    /// Extra environment variables for launched programs
+    pub env: HashMap<String, String>,
+    /// Short names for command lines, in the `[aliases]` table: `ff = "firefox --private-window"`
+    pub aliases: HashMap<String, String>,
+    /// Names always listed first when they match, in this order
+    pub pinned: Vec<String>,
+    /// Commands that ask "Run ...?" before they start
+    pub confirm: Confirm,
+    /// Web search used by the `?` prefix; `{query}` is replaced with the encoded terms
+    pub search_url: String,
+    pub case: CaseMode,
+    /// What Enter runs: the highlighted entry or the typed command line
+    pub enter: EnterPolicy,
+    /// The mouse wheel moves the highlight instead of scrolling the results
+    pub scroll_selects: bool,
+    /// Moving past the last result goes back to the first, and the other way round
+    pub wrap_navigation: bool,
+    /// Close when another window takes the keyboard focus (never while asking for the sudo password)
+    pub close_on_focus_loss: bool,
+    /// Launching again while DeeMenu is open raises the open window instead of starting a second one
+    pub single_instance: bool,
+    /// Start from an empty query when raised again; off keeps the last one
+    pub clear_on_open: bool,
+    /// How to report launch failures that happen after the window may have closed
+    pub notify: Notify,
+    /// Keys for each action, in the `[keys]` table
+    pub keys: Keys,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            layout: Layout::Horizontal,
+            width: None,
+            height: 40.0,
+            lines: 10,
+            max_results: 50,
+            max_name_length: 60,
+            page_size: 10,
+            monitor: MonitorChoice::Primary,
+            position: None,
+            xwayland: false,
+            window_type: WindowType::Normal,
+            accent_color: [217, 70, 239],
+            panel_color: [35, 36, 41],
+            text_color: [255, 255, 255],
+            separator: "|".to_string(),
+            separator_color: [160, 160, 160],
+            corner_radius: 2.0,
+            dim_unfocused: true,
+            padding: [12.0, 6.0],
+            font_size: 14.0,
+            font: None,
+            font_name: None,
+            prompt: String::new(),
+            hint_text: "Run...".to_string(),
+            opacity: 1.0,
+            transparent: false,
+            terminal: None,
+            launcher: Launcher::Direct,
+            strict_exec: false,
+            full_paths: false,
+            cache: true,
+            history: true,
+            extra_paths: Vec::new(),
+            exclude_paths: Vec::new(),
+            blocklist: Vec::new(),
+            allowlist: Vec::new(),
+            env: HashMap::new(),
+            aliases: HashMap::new(),
+            pinned: Vec::new(),
+            confirm: Confirm::default(),
+            search_url: "https://duckduckgo.com/?q={query}".to_string(),
+            case: CaseMode::Smart,
+            enter: EnterPolicy::Auto,
+            scroll_selects: false,
+            wrap_navigation: true,
+            close_on_focus_loss: false,
+            single_instance: false,
+            clear_on_open: true,
+            notify: Notify::NotifySend,
+            keys: Keys::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file (`path`, or the default location), falling back
+    /// to defaults if it is missing or broken.
+    pub fn load(path: Option<&Path>) -> Self {
+        let explicit = path.is_some();
+        let Some(path) = path.map(Path::to_path_buf).or_else(Self::default_path) else {
+            return Self::default();
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                // No config file at the default location is perfectly normal
+                if explicit {
+                    eprintln!("deemenu: warning: can't read {}: {}", path.display(), e);
+                }
+                return Self::default();
+            }
+        };
+
+        match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("deemenu: warning: ignoring malformed {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/deemenu/config.toml`, or `~/.config/deemenu/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("deemenu").join("config.toml"))
+    }
+
+    /// Inner size of the window for the configured layout.
+    /// In the vertical layout `height` is the height of a single row.
+    /// Without a `width` the bar spans `monitor_width`, or 1920 if that is unknown too.
+    pub fn window_size(&self, monitor_width: Option<f32>) -> [f32; 2] {
+        match self.layout {
+            Layout::Horizontal => [self.width.or(monitor_width).unwrap_or(1920.0), self.height],
+            Layout::Vertical => [self.width.unwrap_or(600.0), self.height * (self.lines + 1) as f32],
+        }
+    }
+
+    /// Where the window should sit on the given monitor: `position` if set,
+    /// otherwise pinned top-left for the bar and centered for the vertical box.
+    pub fn window_position(&self, monitor: Option<egui::Rect>) -> egui::Pos2 {
+        let monitor = monitor.unwrap_or(egui::Rect::ZERO);
+
+        if let Some([x, y]) = self.position {
+            return monitor.min + egui::vec2(x, y);
+        }
+
+        match self.layout {
+            Layout::Horizontal => monitor.min,
+            Layout::Vertical => {
+                let size = egui::Vec2::from(self.window_size(Some(monitor.width())));
+                monitor.min + ((monitor.size() - size) / 2.0).max(egui::Vec2::ZERO)
+            }
+        }
+    }
+
+    pub fn x11_window_type(&self) -> egui::X11WindowType {
+        match self.window_type {
+            WindowType::Normal => egui::X11WindowType::Normal,
+            WindowType::Dock => egui::X11WindowType::Dock,
+        }
+    }
+
+    /// Whether the window needs an alpha channel
+    pub fn is_transparent(&self) -> bool {
+        self.transparent || self.opacity < 1.0
+    }
+
+    /// A background color at the configured opacity.
+    pub fn with_opacity(&self, color: egui::Color32) -> egui::Color32 {
+        let alpha = (self.opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+        egui::Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), alpha)
+    }
+
+    pub fn accent(&self) -> egui::Color32 {
+        rgb(self.accent_color)
+    }
+
+    /// Panel background, at the configured opacity
+    pub fn panel(&self) -> egui::Color32 {
+        self.with_opacity(rgb(self.panel_color))
+    }
+
+    pub fn text(&self) -> egui::Color32 {
+        rgb(self.text_color)
+    }
+
+    pub fn separator(&self) -> egui::Color32 {
+        rgb(self.separator_color)
+    }
+
+    /// Space between a result's left edge and its icon or name
+    pub fn inset(&self) -> f32 {
+        self.padding[0] / 2.0
+    }
+}
+
+fn rgb([r, g, b]: [u8; 3]) -> egui::Color32 {
+    egui::Color32::from_rgb(r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_file_keeps_the_other_defaults() {
+        let config: Config = toml::from_str("layout = \"vertical\"\nheight = 32.0\n\n[aliases]\nff = \"firefox --private-window\"\n").unwrap();
+        assert!(config.layout == Layout::Vertical);
+        assert_eq!(config.height, 32.0);
+        assert_eq!(config.aliases["ff"], "firefox --private-window");
+
+        let defaults = Config::default();
+        assert_eq!(config.max_results, defaults.max_results);
+        assert_eq!(config.search_url, "https://duckduckgo.com/?q={query}");
+        assert!(config.history && config.cache && config.clear_on_open && config.wrap_navigation);
+        assert!(config.case == CaseMode::Smart && config.enter == EnterPolicy::Auto && config.launcher == Launcher::Direct);
+        assert_eq!(config.window_size(None), [600.0, 32.0 * 11.0]);
+    }
+
+    #[test]
+    fn reads_named_and_numbered_choices() {
+        let config: Config = toml::from_str("monitor = \"cursor\"\nlauncher = \"systemd-run\"\nnotify = \"none\"\n").unwrap();
+        assert!(config.monitor == MonitorChoice::Cursor && config.launcher == Launcher::SystemdRun && config.notify == Notify::None);
+        let config: Config = toml::from_str("monitor = 2\n\n[confirm]\nsudo = true\n").unwrap();
+        assert!(config.monitor == MonitorChoice::Index(2) && config.confirm.sudo);
+
+        assert!(toml::from_str::<Config>("monitor = \"left\"").is_err());
+        assert!(toml::from_str::<Config>("layout = \"diagonal\"").is_err());
+    }
+
+    #[test]
+    fn bad_or_missing_file_falls_back_to_the_defaults() {
+        let path = env::temp_dir().join(format!("deemenu-config-test-{}.toml", std::process::id()));
+        fs::write(&path, "height = \"tall\"\nlines = 3\n").unwrap();
+        let config = Config::load(Some(&path));
+        assert_eq!((config.height, config.lines), (40.0, 10));
+
+        fs::remove_file(&path).unwrap();
+        let config = Config::load(Some(&path));
+        assert!(config.layout == Layout::Horizontal);
+        assert_eq!(config.hint_text, "Run...");
+    }
+}
@@ -0,0 +1,1708 @@
+use crate::config::{CaseMode, Config, Confirm, EnterPolicy};
+use crate::prefix::{self, Action, Prefix, Results};
+use crate::exports::Labels;
+use crate::{cache, desktop, files, shell, web};
+use icu_normalizer::DecomposingNormalizerBorrowed;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// What is known about a result, shared between the full list and the
+/// filtered results so filtering never copies strings.
+pub struct Entry {
+    pub name: String,
+    /// `name` folded (see `fold`) once at scan time, so filtering doesn't allocate per keystroke
+    pub lower: String,
+    /// Lowercased first letters of the words in `name`, for acronym matches (`vsc`)
+    pub initials: String,
+    /// Folded GenericName/Keywords/Comment of a desktop entry; matches rank below `name`
+    pub metadata: String,
+    /// What to run, when that isn't simply `name` (desktop entries)
+    pub exec: Option<String>,
+    pub terminal: bool,
+    /// Image file drawn next to the name (desktop entries with a resolvable `Icon`)
+    pub icon: Option<PathBuf>,
+    /// Where a binary was found, when scanned with `full_paths`
+    pub path: Option<PathBuf>,
+    /// Lowercased freedesktop categories of a desktop entry, for `@category` queries
+    pub categories: Vec<String>,
+    /// File types a desktop entry opens, for "open with"
+    pub mime_types: Vec<String>,
+}
+
+impl Entry {
+    /// Whether this app opens files of type `mime`, listed as is or as `major/*`.
+    pub fn opens(&self, mime: &str) -> bool {
+        let major = mime.split('/').next().unwrap_or_default();
+        self.mime_types.iter().any(|listed| listed == mime || listed.strip_suffix("/*") == Some(major))
+    }
+}
+
+/// Something that can be picked from the list: a binary from PATH, a desktop
+/// entry, or a synthetic result (calculator, path completion).
+#[derive(Clone)]
+pub struct Candidate {
+    pub entry: Arc<Entry>,
+    /// Byte range of `name` that matched the query, highlighted in the results
+    pub matched: Option<Range<usize>>,
+}
+
+impl Deref for Candidate {
+    type Target = Entry;
+
+    fn deref(&self) -> &Entry {
+        &self.entry
+    }
+}
+
+impl Candidate {
+    pub fn new(name: String) -> Self {
+        let lower = fold(&name);
+        let initials = initials(&name);
+        let entry = Entry {
+            name, lower, initials, metadata: String::new(), exec: None, terminal: false, icon: None, path: None, categories: Vec::new(),
+            mime_types: Vec::new(),
+        };
+        Self { entry: Arc::new(entry), matched: None }
+    }
+
+    pub fn from_desktop(entry: &desktop::DesktopEntry, icon: Option<PathBuf>) -> Self {
+        let metadata = entry.generic_name.iter()
+            .chain(&entry.keywords)
+            .chain(&entry.comment)
+            .map(|field| fold(field))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let entry = Entry {
+            lower: fold(&entry.name),
+            initials: initials(&entry.name),
+            name: entry.name.clone(),
+            metadata,
+            exec: Some(entry.command()),
+            terminal: entry.terminal,
+            icon,
+            path: None,
+            categories: entry.categories.iter().map(|category| category.to_lowercase()).collect(),
+            mime_types: entry.mime_types.clone(),
+        };
+        Self { entry: Arc::new(entry), matched: None }
+    }
+
+    /// A binary found at `path` (`full_paths`), run by that exact path. One
+    /// that an earlier PATH directory `shadows` is listed with its directory.
+    pub fn at_path(name: String, path: PathBuf, shadowed: bool) -> Self {
+        let dir = path.parent().unwrap_or(Path::new("/")).to_string_lossy();
+        let mut candidate = Self::new(if shadowed { format!("{} ({})", name, dir) } else { name });
+
+        let entry = Arc::get_mut(&mut candidate.entry).unwrap();
+        entry.metadata = fold(&path.to_string_lossy());
+        entry.exec = Some(shell::quote(&path.to_string_lossy()));
+        entry.path = Some(path);
+        candidate
+    }
+
+    /// A Flatpak or Snap launcher at `path`, listed under the `label` of its
+    /// exported desktop entry and still found by its own name.
+    pub fn exported(label: String, path: PathBuf) -> Self {
+        let mut candidate = Self::new(label);
+
+        let entry = Arc::get_mut(&mut candidate.entry).unwrap();
+        entry.metadata = fold(&path.file_name().unwrap_or_default().to_string_lossy());
+        entry.exec = Some(shell::quote(&path.to_string_lossy()));
+        entry.path = Some(path);
+        candidate
+    }
+
+    /// A `--dmenu` line: listed as the field `fields` show, printed as the one they accept.
+    pub fn choice(line: &str, fields: &Fields) -> Self {
+        let shown = fields.field(line, fields.with_nth).trim();
+        let printed = fields.field(line, fields.accept_nth);
+        let mut candidate = Self::new(shown.to_string());
+        if printed != shown {
+            Arc::get_mut(&mut candidate.entry).unwrap().exec = Some(printed.to_string());
+        }
+        candidate
+    }
+
+    /// An `[aliases]` entry: listed under its own name, and also found by what it runs.
+    pub fn alias(name: &str, expansion: &str) -> Self {
+        let mut candidate = Self::new(name.to_string());
+        Arc::get_mut(&mut candidate.entry).unwrap().metadata = fold(expansion);
+        candidate
+    }
+
+    pub fn command(&self) -> &str {
+        self.exec.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// How `--dmenu` lines split into fields, like fzf's `--delimiter` and `--with-nth`.
+#[derive(Default)]
+pub struct Fields {
+    /// Between fields; runs of whitespace when unset
+    pub delimiter: Option<String>,
+    /// The 1-based field listed; the whole line when unset
+    pub with_nth: Option<usize>,
+    /// The 1-based field printed on selection; the whole line when unset
+    pub accept_nth: Option<usize>,
+}
+
+impl Fields {
+    /// Field `n` of `line`, or all of it when there is no such field.
+    fn field<'a>(&self, line: &'a str, n: Option<usize>) -> &'a str {
+        let Some(index) = n.and_then(|n| n.checked_sub(1)) else { return line };
+        let field = match &self.delimiter {
+            Some(delimiter) => line.split(delimiter.as_str()).nth(index),
+            None => line.split_whitespace().nth(index),
+        };
+        field.unwrap_or(line)
+    }
+}
+
+/// The first letter of each word, lowercased: words start after `-`, `_`, `.`
+/// or a space, and at camelCase capitals (`visual-studio-code` and
+/// `LibreOffice` give `vsc` and `lo`).
+pub fn initials(name: &str) -> String {
+    let firsts: String = name.char_indices()
+        .filter(|&(i, c)| c.is_alphanumeric() && starts_word(name, i))
+        .map(|(_, c)| c)
+        .collect();
+    fold(&firsts)
+}
+
+/// `text` lowercased and without accents (`Évolution` gives `evolution`, `Straße`
+/// gives `strasse`), so queries typed without them still match. Letters are
+/// decomposed (NFKD) and the combining marks dropped.
+pub fn fold(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_ascii_lowercase();
+    }
+
+    let lower: String = text.chars().flat_map(char::to_lowercase).collect();
+    let decomposed = DecomposingNormalizerBorrowed::new_nfkd().normalize(&lower);
+    let mut folded = String::with_capacity(decomposed.len());
+    for c in decomposed.chars() {
+        match c {
+            c if is_combining_mark(c) => {}
+            // Letters of their own rather than accented ones, so NFKD keeps them
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            'þ' => folded.push_str("th"),
+            'ø' => folded.push('o'),
+            'đ' | 'ð' => folded.push('d'),
+            'ħ' => folded.push('h'),
+            'ı' => folded.push('i'),
+            'ł' => folded.push('l'),
+            'ŧ' => folded.push('t'),
+            c => folded.push(c),
+        }
+    }
+    folded
+}
+
+/// Whether `c` is in one of the combining diacritical mark blocks.
+fn is_combining_mark(c: char) -> bool {
+    matches!(c, '\u{300}'..='\u{36f}' | '\u{1ab0}'..='\u{1aff}' | '\u{1dc0}'..='\u{1dff}' | '\u{20d0}'..='\u{20ff}' | '\u{fe20}'..='\u{fe2f}')
+}
+
+/// Whether a word begins at byte `index` of `name`, as `initials` counts words.
+/// The last capital of a run followed by lowercase starts one too (`VSCode`).
+fn starts_word(name: &str, index: usize) -> bool {
+    let mut after = name[index..].chars();
+    let (Some(prev), Some(c)) = (name[..index].chars().next_back(), after.next()) else {
+        return true;
+    };
+    matches!(prev, '-' | '_' | '.' | ' ')
+        || (c.is_uppercase() && (prev.is_lowercase() || (prev.is_uppercase() && after.next().is_some_and(char::is_lowercase))))
+}
+
+#[derive(PartialEq)]
+pub enum AppMode {
+    Search,
+    SudoPassword,
+    /// Waiting for Enter (or Esc) before carrying out `Core::pending_action`
+    Confirm,
+}
+
+/// How many `=` results stay listed under the calculator
+const MAX_CALCULATIONS: usize = 5;
+
+/// How many earlier command lines are suggested under a program
+const MAX_INVOCATIONS: usize = 3;
+
+/// Shift+Enter on a file in path mode: the apps that open its type are listed
+/// instead, and Enter opens the file with the highlighted one.
+pub struct OpenWith {
+    pub path: PathBuf,
+    pub mime: String,
+    /// The path query to go back to on Esc
+    query: String,
+}
+
+/// The launcher without its window: what is listed, typed and highlighted,
+/// and what Enter should do about it. `DeeMenu` draws this and carries out
+/// the `Action`s it returns.
+pub struct Core {
+    pub all_executables: Vec<Candidate>,
+    pub filtered_executables: Vec<Candidate>,
+    pub search_query: String,
+    /// What was typed before Tab completion replaced it; Esc brings it back
+    pub typed_query: Option<String>,
+    pub selected_index: usize,
+    pub mode: AppMode,
+    pub pending_sudo_command: String,
+    /// How often each entry was launched this run; lifts it to the top of the empty-query list
+    pub launch_counts: HashMap<String, u32>,
+    /// Special modes keyed on how the query starts, see `prefix::registry`
+    pub prefixes: Vec<Box<dyn Prefix>>,
+    pub case: CaseMode,
+    pub enter: EnterPolicy,
+    /// `wrap_navigation`: stepping past either end goes round to the other one
+    pub wrap: bool,
+    pub aliases: HashMap<String, String>,
+    /// Names listed before everything else that matches, in this order
+    pub pinned: Vec<String>,
+    /// Patterns of names the scan never lists
+    pub blocklist: Vec<String>,
+    /// Patterns of the only names the scan lists, when not empty
+    pub allowlist: Vec<String>,
+    /// `--recent`: launched names, latest first; with nothing typed only these are listed
+    pub recent: Vec<String>,
+    /// Command lines launched with arguments before, most used first; listed under their program
+    pub invocations: Vec<String>,
+    pub confirm: Confirm,
+    /// What `AppMode::Confirm` asks about, and whether it was to run attached
+    pub pending_action: Option<(Action, bool)>,
+    /// Ctrl+S: the highlighted entry runs with sudo; editing the query clears it
+    pub sudo_selection: bool,
+    /// Ctrl+Space or Ctrl+click: names Enter launches together, in the order they were marked
+    pub marked: Vec<String>,
+    pub open_with: Option<OpenWith>,
+    /// `=` expressions entered so far and what they came to, oldest first
+    pub calculations: Vec<(String, String)>,
+}
+
+impl Core {
+    /// Starts out listing only the configured aliases; the scan adds the rest through `merge`.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            all_executables: config.aliases.iter().map(|(name, expansion)| Candidate::alias(name, expansion)).collect(),
+            filtered_executables: Vec::new(),
+            search_query: String::new(),
+            typed_query: None,
+            selected_index: 0,
+            mode: AppMode::Search,
+            pending_sudo_command: String::new(),
+            launch_counts: HashMap::new(),
+            prefixes: prefix::registry(&config.search_url),
+            case: config.case,
+            enter: config.enter,
+            wrap: config.wrap_navigation,
+            aliases: config.aliases.clone(),
+            pinned: config.pinned.clone(),
+            blocklist: config.blocklist.clone(),
+            allowlist: config.allowlist.clone(),
+            recent: Vec::new(),
+            invocations: Vec::new(),
+            confirm: config.confirm.clone(),
+            pending_action: None,
+            sudo_selection: false,
+            marked: Vec::new(),
+            open_with: None,
+            calculations: Vec::new(),
+        }
+    }
+
+    /// `--dmenu`: pick from the given lines (see `Candidate::choice`), in their order, without any prefixes or pins.
+    pub fn use_choices(&mut self, choices: Vec<Candidate>) {
+        self.prefixes.clear();
+        self.pinned.clear();
+        self.invocations.clear();
+        self.all_executables = choices;
+        self.update_filter();
+    }
+
+    /// Adds scanned candidates the `blocklist`/`allowlist` let through, keeping
+    /// the list sorted and the highlight on the same name while entries are
+    /// inserted around it.
+    pub fn merge(&mut self, candidates: Vec<Candidate>) {
+        let candidates: Vec<Candidate> = candidates.into_iter().filter(|c| self.is_allowed(c)).collect();
+        self.all_executables.extend(candidates);
+        self.all_executables.sort_by(|a, b| a.lower.cmp(&b.lower).then_with(|| a.name.cmp(&b.name)));
+
+        let selected = self.filtered_executables.get(self.selected_index).map(|c| c.name.clone());
+        self.update_filter();
+        if let Some(i) = selected.and_then(|name| self.filtered_executables.iter().position(|c| c.name == name)) {
+            self.selected_index = i;
+        }
+    }
+
+    /// Replaces the query, e.g. when a prefix steps into a directory.
+    pub fn set_query(&mut self, query: String) {
+        self.search_query = query;
+        self.query_edited();
+    }
+
+    /// Call after `search_query` changed from typing or pasting: forgets
+    /// any Tab completion and starts over from the best match.
+    pub fn query_edited(&mut self) {
+        self.typed_query = None;
+        self.sudo_selection = false;
+        self.selected_index = 0;
+        self.update_filter();
+    }
+
+    pub fn update_filter(&mut self) {
+        let (category, query) = split_category(self.search_query.trim());
+
+        // Special modes list their own entries or filter on part of the query
+        let results = match self.prefix_for(query) {
+            _ if self.open_with.is_some() => Results::Filter(query.to_string()),
+            Some(prefix) if category.is_none() => prefix.results(query),
+            _ => Results::Filter(query.to_string()),
+        };
+
+        let filtered = matches!(results, Results::Filter(_));
+        self.filtered_executables = match results {
+            Results::Entries(entries) => entries,
+            Results::Filter(text) => self.matching_candidates(&text, category.as_deref()),
+        };
+
+        // `--recent` lists only the history, in its own order; typing searches everything again
+        let recent_only = filtered && query.is_empty() && !self.recent.is_empty();
+        if recent_only {
+            self.filtered_executables = self.recent.iter()
+                .filter_map(|name| self.all_executables.iter().find(|c| c.name == *name).cloned())
+                .collect();
+        } else if query.is_empty() && !self.launch_counts.is_empty() {
+            // With nothing typed, what was used this run comes first (stable, so ties stay alphabetical)
+            let counts = &self.launch_counts;
+            self.filtered_executables.sort_by_key(|c| std::cmp::Reverse(counts.get(&c.name).copied().unwrap_or(0)));
+        }
+
+        // Pinned matches go before all of that, in the configured order
+        if filtered && !recent_only && !self.pinned.is_empty() {
+            let pinned = &self.pinned;
+            self.filtered_executables.sort_by_key(|c| pinned.iter().position(|name| *name == c.name).unwrap_or(pinned.len()));
+        }
+        if let Some(open_with) = &self.open_with {
+            self.filtered_executables.retain(|c| c.opens(&open_with.mime));
+        } else if filtered && !recent_only && category.is_none() {
+            suggest_invocations(&mut self.filtered_executables, &self.invocations, &fold(query));
+        }
+        log::trace!("{:?} matches {} entries", query, self.filtered_executables.len());
+
+        // Safety bounds
+        if self.filtered_executables.is_empty() {
+            self.selected_index = 0;
+        } else if self.selected_index >= self.filtered_executables.len() {
+            self.selected_index = self.filtered_executables.len() - 1;
+        }
+    }
+
+    /// The command line Enter runs when it has arguments typed (or picked from
+    /// `invocations`) rather than being an entry's own, for history to suggest again.
+    pub fn invocation(&self) -> Option<String> {
+        let (category, query) = split_category(self.search_query.trim());
+        if category.is_some() || self.open_with.is_some() || !self.marked.is_empty() || self.prefix_for(query).is_some() {
+            return None;
+        }
+        let (command, _) = self.entered_command(query, self.filtered_executables.get(self.selected_index));
+        let own = self.all_executables.iter().any(|c| c.command() == command);
+        (command.contains(char::is_whitespace) && !own).then_some(command)
+    }
+
+    /// Whether `candidate`'s name (or the program a desktop entry runs) passes
+    /// the `blocklist` and, when there is one, the `allowlist`.
+    pub fn is_allowed(&self, candidate: &Candidate) -> bool {
+        let program = candidate.exec.as_deref().and_then(program_name);
+        let hit = |patterns: &[String]| patterns.iter().any(|pattern| {
+            glob_match(pattern, &candidate.name) || program.as_deref().is_some_and(|program| glob_match(pattern, program))
+        });
+        !hit(&self.blocklist) && (self.allowlist.is_empty() || hit(&self.allowlist))
+    }
+
+    /// With an `allowlist`, whether `command` is something listed: an entry's
+    /// own command line, or a listed program or alias with arguments that need no shell.
+    fn permits(&self, command: &str) -> bool {
+        if self.allowlist.is_empty() {
+            return true;
+        }
+        let head = command.split_whitespace().next().unwrap_or_default();
+        self.all_executables.iter().any(|c| {
+            c.command() == command || (c.name == head && (c.exec.is_none() || c.path.is_some()) && !shell::needs_shell(command))
+        })
+    }
+
+    pub fn is_pinned(&self, candidate: &Candidate) -> bool {
+        self.pinned.contains(&candidate.name)
+    }
+
+    pub fn is_marked(&self, candidate: &Candidate) -> bool {
+        self.marked.contains(&candidate.name)
+    }
+
+    /// Ctrl+Space: adds the highlighted entry to the ones Enter launches together,
+    /// or takes it out again. Only programs and apps can be marked, not prefix results.
+    pub fn toggle_mark(&mut self) {
+        if self.prefix_for(self.search_query.trim()).is_some() || self.open_with.is_some() {
+            return;
+        }
+        let Some(selected) = self.filtered_executables.get(self.selected_index) else { return };
+        match self.marked.iter().position(|name| *name == selected.name) {
+            Some(i) => {
+                self.marked.remove(i);
+            }
+            None => self.marked.push(selected.name.clone()),
+        }
+    }
+
+    /// The highlighted file in path mode, which Shift+Enter offers to open with another app.
+    pub fn selected_file(&self) -> Option<PathBuf> {
+        let query = self.search_query.trim();
+        if self.open_with.is_some() || !files::is_path_query(query) {
+            return None;
+        }
+        // A command line (`/bin/sh -c ...`) names no file, but spaces inside a path are fine
+        let target = self.filtered_executables.get(self.selected_index).map_or(query, |c| c.name.as_str());
+        let path = files::expand_tilde(target);
+        (!target.ends_with('/') && path.is_file()).then_some(path)
+    }
+
+    /// Lists the apps that open files of type `mime`, to pick one for `path`.
+    pub fn start_open_with(&mut self, path: PathBuf, mime: String) {
+        let query = std::mem::take(&mut self.search_query);
+        self.open_with = Some(OpenWith { path, mime, query });
+        self.query_edited();
+    }
+
+    /// Esc: back to the path the apps were listed for. False if not picking an app.
+    pub fn cancel_open_with(&mut self) -> bool {
+        let Some(open_with) = self.open_with.take() else { return false };
+        self.set_query(open_with.query);
+        true
+    }
+
+    /// The prefix plugin handling the query, if any.
+    pub fn prefix_for(&self, query: &str) -> Option<&dyn Prefix> {
+        self.prefixes.iter().find(|prefix| prefix.matches(query)).map(|prefix| prefix.as_ref())
+    }
+
+    /// Regular candidates matching `text`, best first: names where it starts a
+    /// word (see `initials`), then acronym hits (`vsc` for `visual-studio-code`), then names containing
+    /// it anywhere, then entries that only match through their metadata.
+    /// A `category` (lowercase) leaves only desktop entries with a category starting with it.
+    fn matching_candidates(&self, text: &str, category: Option<&str>) -> Vec<Candidate> {
+        let in_category = |c: &&Candidate| category.is_none_or(|wanted| c.categories.iter().any(|name| name.starts_with(wanted)));
+        if text.is_empty() {
+            return self.all_executables.iter().filter(in_category).cloned().collect();
+        }
+
+        let case_sensitive = match self.case {
+            CaseMode::Sensitive => true,
+            CaseMode::Insensitive => false,
+            // Smart case: an uppercase letter means the user is being precise
+            CaseMode::Smart => text.chars().any(char::is_uppercase),
+        };
+        let lower_text = fold(text);
+
+        let name_match = |candidate: &Candidate| if case_sensitive {
+            candidate.name.find(text).map(|start| start..start + text.len())
+        } else {
+            candidate.lower.find(&lower_text).map(|start| start..start + lower_text.len())
+        };
+
+        // Word boundaries are what make an acronym, so initials always ignore case
+        let acronym = lower_text.chars().count() > 1 && !lower_text.contains(char::is_whitespace);
+
+        let mut hits: Vec<(u8, Candidate)> = self.all_executables.iter().filter(in_category).filter_map(|c| {
+            if let Some(range) = name_match(c) {
+                // Lowercasing can change byte lengths; only then do offsets not carry over to `name`
+                let same_offsets = case_sensitive || c.lower.len() == c.name.len();
+                let rank = if range.start == 0 || (same_offsets && starts_word(&c.name, range.start)) { 0 } else { 2 };
+                return Some((rank, Candidate { matched: same_offsets.then_some(range), ..c.clone() }));
+            }
+            if acronym && c.initials.starts_with(&lower_text) {
+                return Some((1, c.clone()));
+            }
+            (!c.metadata.is_empty() && c.metadata.contains(&lower_text)).then(|| (3, c.clone()))
+        }).collect();
+
+        // Stable, so each rank keeps the alphabetical scan order
+        hits.sort_by_key(|(rank, _)| *rank);
+        hits.into_iter().map(|(_, c)| c).collect()
+    }
+
+    /// Wraps around to the first result after the last one, or stays on the
+    /// last without `wrap`.
+    pub fn select_next(&mut self) {
+        let len = self.filtered_executables.len();
+        if len > 0 && (self.wrap || self.selected_index + 1 < len) {
+            self.selected_index = (self.selected_index + 1) % len;
+        }
+    }
+
+    /// Wraps around to the last result before the first one, or stays on the
+    /// first without `wrap`.
+    pub fn select_previous(&mut self) {
+        let len = self.filtered_executables.len();
+        if len > 0 && (self.wrap || self.selected_index > 0) {
+            self.selected_index = (self.selected_index + len - 1) % len;
+        }
+    }
+
+    /// Index of the first result drawn. Only the page holding the highlight
+    /// is shown; moving past its end shows the next one.
+    pub fn page_start(&self, page_size: usize) -> usize {
+        let page_size = page_size.max(1);
+        self.selected_index / page_size * page_size
+    }
+
+    /// The result Alt+1..9 picks: the Nth one on screen, if there are that many.
+    pub fn quick_pick(&self, digit: usize, page_size: usize) -> Option<usize> {
+        let page_end = self.page_start(page_size) + page_size.max(1);
+        let index = self.page_start(page_size) + digit.checked_sub(1)?;
+        (index < page_end.min(self.filtered_executables.len())).then_some(index)
+    }
+
+    /// Fills the search box with the highlighted name, shell-completion style,
+    /// keeping any `sudo `/`term:` prefix. Returns false if there was nothing to
+    /// complete (the query already is the highlighted name, or has arguments).
+    pub fn complete_selection(&mut self) -> bool {
+        if self.copies_selection() { return false; }
+        let Some(selected) = self.filtered_executables.get(self.selected_index).map(|c| c.name.clone()) else {
+            return false;
+        };
+
+        let query = self.search_query.trim_start();
+        let (prefix, typed) = if let Some(rest) = prefix::strip_command_word(query, "sudo") {
+            ("sudo ", rest)
+        } else if let Some(rest) = prefix::strip_prefix_ignore_case(query, "term:") {
+            ("term: ", rest.trim_start())
+        } else {
+            ("", query)
+        };
+
+        if typed == selected || (typed.contains(' ') && !files::is_path_query(typed)) {
+            return false;
+        }
+
+        let completed = format!("{}{}", prefix, selected);
+        self.typed_query.get_or_insert(std::mem::replace(&mut self.search_query, completed));
+        self.update_filter();
+
+        // Keep the highlight on the completed name rather than jumping to the first hit
+        self.selected_index = self.filtered_executables.iter().position(|c| c.name == selected).unwrap_or(0);
+        true
+    }
+
+    /// Undoes Tab completion, putting back what the user typed.
+    /// Returns false if nothing was completed since they last typed.
+    pub fn restore_typed_query(&mut self) -> bool {
+        let Some(typed) = self.typed_query.take() else { return false };
+        self.search_query = typed;
+        self.selected_index = 0;
+        self.update_filter();
+        true
+    }
+
+    /// The page to open in the browser, for `?search terms` or a typed URL/domain.
+    /// Domains only count when no binary matches, so names like `foo.sh` still launch.
+    pub fn web_target(&self, search_url: &str) -> Option<String> {
+        let query = self.search_query.trim();
+
+        if let Some(terms) = query.strip_prefix('?') {
+            let terms = terms.trim();
+            return (!terms.is_empty()).then(|| web::search_url(search_url, terms));
+        }
+
+        let url = web::as_url(query)?;
+        (query.contains("://") || self.filtered_executables.is_empty()).then_some(url)
+    }
+
+    pub fn is_calc_query(&self) -> bool {
+        self.search_query.trim_start().starts_with('=')
+    }
+
+    /// Enter on an `=` expression: notes it with its result in `calculations`
+    /// and leaves just `=` for the next one. Returns the number to copy.
+    pub fn keep_calculation(&mut self) -> Option<String> {
+        if !self.is_calc_query() {
+            return None;
+        }
+        let result = self.filtered_executables.first()?.name.clone();
+        let number = result.split(' ').next()?.to_string();
+        let expr = self.search_query.trim().trim_start_matches('=').trim().to_string();
+
+        self.calculations.push((expr, result));
+        if self.calculations.len() > MAX_CALCULATIONS {
+            self.calculations.remove(0);
+        }
+        self.set_query("=".to_string());
+        Some(number)
+    }
+
+    /// Whether Enter copies the highlighted result (`=` calculator, `:` emoji)
+    /// instead of running it, so Tab and clicks must leave the query alone.
+    pub fn copies_selection(&self) -> bool {
+        self.is_calc_query() || self.search_query.trim_start().starts_with(':')
+    }
+
+    /// `--dmenu`: the line Enter prints, the highlighted choice (its accepted
+    /// field) or (`typed`, like dmenu's Shift+Enter) the typed text even when something matches.
+    pub fn dmenu_choice(&self, typed: bool) -> Option<&str> {
+        let choice = match self.filtered_executables.get(self.selected_index) {
+            Some(selected) if !typed => selected.command(),
+            _ => self.search_query.trim(),
+        };
+        (!choice.is_empty()).then_some(choice)
+    }
+
+    /// What Enter does in search mode, counting the launch of the highlighted entry
+    /// (or of the marked ones, which launch together whatever is typed).
+    pub fn resolve(&mut self) -> Action {
+        if !self.marked.is_empty() {
+            return self.resolve_marked();
+        }
+        if let Some(open_with) = &self.open_with {
+            let Some(app) = self.filtered_executables.get(self.selected_index) else { return Action::Nothing };
+            let command = format!("{} {}", app.command(), shell::quote(&open_with.path.to_string_lossy()));
+            let in_terminal = app.terminal;
+            *self.launch_counts.entry(app.name.clone()).or_default() += 1;
+            return Action::Launch { command, in_terminal };
+        }
+
+        let (category, query) = split_category(self.search_query.trim());
+        let selected = self.filtered_executables.get(self.selected_index);
+
+        // Prefixed queries (calc, web, paths, sudo, term:) decide for themselves;
+        // anything else runs the highlighted entry or the typed command line.
+        let action = match self.prefix_for(query) {
+            Some(prefix) if category.is_none() => prefix.run(query, selected),
+            _ => {
+                let (command, in_terminal) = self.entered_command(query, selected);
+                Action::Launch { command, in_terminal }
+            }
+        };
+
+        let action = match action {
+            Action::Launch { ref command, .. } | Action::Sudo(ref command) if !self.permits(command) => {
+                Action::Error(format!("not allowed: {}", command))
+            }
+            other => other,
+        };
+
+        // Aliases expand here so `sudo ff` and `term: ff` work too
+        let action = match action {
+            Action::Launch { command, in_terminal } => {
+                Action::Launch { command: prefix::expand_alias(&command, &self.aliases), in_terminal }
+            }
+            Action::Sudo(command) => Action::Sudo(prefix::expand_alias(&command, &self.aliases)),
+            other => other,
+        };
+
+        let action = match action {
+            Action::Launch { command, .. } if self.sudo_selection => Action::Sudo(command),
+            other => other,
+        };
+        log::debug!("{:?} resolved to {:?}", query, action);
+
+        if let (Action::Launch { .. } | Action::Sudo(_), Some(selected)) = (&action, selected) {
+            *self.launch_counts.entry(selected.name.clone()).or_default() += 1;
+        }
+        action
+    }
+
+    /// Ctrl+S: switches whether Enter runs the highlighted entry with sudo.
+    pub fn toggle_sudo(&mut self) {
+        self.sudo_selection = !self.sudo_selection;
+    }
+
+    fn resolve_marked(&mut self) -> Action {
+        let mut launches = Vec::new();
+        for name in &self.marked {
+            let Some(candidate) = self.all_executables.iter().find(|c| c.name == *name) else { continue };
+            let command = prefix::expand_alias(candidate.command(), &self.aliases);
+            launches.push(Action::Launch { command, in_terminal: candidate.terminal });
+            *self.launch_counts.entry(name.clone()).or_default() += 1;
+        }
+        log::debug!("marked {:?} resolved to {:?}", self.marked, launches);
+        Action::Batch(launches)
+    }
+
+    /// What Enter runs for an unprefixed `query` under the `enter` policy, and
+    /// whether it wants a terminal.
+    fn entered_command(&self, query: &str, selected: Option<&Candidate>) -> (String, bool) {
+        let typed = (query.to_string(), false);
+        let highlighted = |candidate: &Candidate| (candidate.command().to_string(), candidate.terminal);
+
+        match self.enter {
+            EnterPolicy::Auto => prefix::chosen_command(query, selected),
+            EnterPolicy::Selection => selected.map_or(typed, highlighted),
+            EnterPolicy::Exact => {
+                // Programs and aliases, not desktop entries, whose names aren't commands
+                let head = query.split_whitespace().next().unwrap_or_default();
+                let exists = self.all_executables.iter().any(|c| c.name == head && (c.exec.is_none() || c.path.is_some()));
+                if exists { typed } else { selected.map_or(typed, highlighted) }
+            }
+            EnterPolicy::Raw => typed,
+        }
+    }
+
+    /// Whether the `[confirm]` settings want `action` confirmed before it is carried out.
+    pub fn needs_confirmation(&self, action: &Action) -> bool {
+        match action {
+            Action::Launch { command, .. } => self.confirm.wants(command, false),
+            Action::Sudo(command) => self.confirm.wants(command, true),
+            Action::Batch(actions) => actions.iter().any(|action| self.needs_confirmation(action)),
+            _ => false,
+        }
+    }
+
+    /// Holds `action` back until `confirmed` (Enter) or `cancel_confirmation` (Esc).
+    pub fn ask_confirmation(&mut self, action: Action, attached: bool) {
+        self.pending_action = Some((action, attached));
+        self.mode = AppMode::Confirm;
+    }
+
+    /// The held back action, now to be carried out; back in search mode.
+    pub fn confirmed(&mut self) -> Option<(Action, bool)> {
+        self.mode = AppMode::Search;
+        self.pending_action.take()
+    }
+
+    pub fn cancel_confirmation(&mut self) {
+        self.mode = AppMode::Search;
+        self.pending_action = None;
+    }
+
+    /// Switches to the password prompt for `command`.
+    pub fn ask_sudo_password(&mut self, command: String) {
+        self.pending_sudo_command = command;
+        self.mode = AppMode::SudoPassword;
+        self.selected_index = 0;
+    }
+}
+
+/// Whether `name` fits `pattern`, where `*` stands for any run of characters.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut pieces = pattern.split('*');
+    let first = pieces.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else { return false };
+
+    let mut pieces: Vec<&str> = pieces.collect();
+    let Some(last) = pieces.pop() else { return rest.is_empty() };
+    for piece in pieces {
+        match rest.find(piece) {
+            Some(i) => rest = &rest[i + piece.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The file name of the program a command line starts with, e.g. `firefox` for `/usr/bin/firefox %u`.
+fn program_name(command: &str) -> Option<String> {
+    let program = shell::split(command)?.into_iter().next()?;
+    Some(Path::new(&program).file_name()?.to_string_lossy().into_owned())
+}
+
+/// Lists earlier `invocations` right after their program's entry in `results`, or
+/// first when they start with what is `typed` (`mpv --lo`) and the program isn't listed.
+fn suggest_invocations(results: &mut Vec<Candidate>, invocations: &[String], typed: &str) {
+    let mut shown: HashMap<&str, usize> = HashMap::new();
+    let mut first = 0;
+    for line in invocations {
+        let program = line.split_whitespace().next().unwrap_or_default();
+        let count = shown.entry(program).or_default();
+        if *count == MAX_INVOCATIONS || results.iter().any(|c| c.name == *line) {
+            continue;
+        }
+        let position = results.iter().position(|c| c.name == program);
+        let at = match position {
+            Some(i) => i + 1 + *count,
+            None if !typed.is_empty() && fold(line).starts_with(typed) => first,
+            None => continue,
+        };
+        *count += 1;
+        if position.is_none() {
+            first += 1;
+        }
+        results.insert(at, Candidate::new(line.clone()));
+    }
+}
+
+/// Splits `@network fire` into the lowercased category and the text to search for.
+fn split_category(query: &str) -> (Option<String>, &str) {
+    match query.strip_prefix('@') {
+        Some(rest) => {
+            let (category, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            (Some(category.to_lowercase()), text.trim_start())
+        }
+        None => (None, query),
+    }
+}
+
+/// Directories to look for binaries in: PATH, standard Linux directories
+/// and the configured `extra_paths`, minus `exclude_paths`.
+pub fn scan_dirs(config: &Config) -> Vec<String> {
+    let path_var = env::var("PATH").unwrap_or_default();
+    if path_var.trim().is_empty() {
+        eprintln!("deemenu: warning: PATH is empty or unset, only scanning the standard directories");
+    }
+    dirs_to_scan(&path_var, config)
+}
+
+/// `scan_dirs` for the given PATH value.
+fn dirs_to_scan(path_var: &str, config: &Config) -> Vec<String> {
+    // 1. Get paths from Environment (an empty entry would mean the working directory)
+    let mut paths_to_scan: Vec<String> = Vec::new();
+    for p in env::split_paths(path_var) {
+        let p = normalize_dir(&p.to_string_lossy());
+        if !p.is_empty() && !paths_to_scan.contains(&p) {
+            paths_to_scan.push(p);
+        }
+    }
+
+    // 2. Force add standard directories (to catch /usr/bin if PATH is minimal)
+    let fallback_paths = [
+        "/usr/bin",
+        "/usr/local/bin",
+        "/bin",
+        "/snap/bin",
+        "/var/lib/flatpak/exports/bin",
+        "/sbin",
+        "/usr/sbin"
+    ];
+
+    for fallback in fallback_paths {
+        let p = fallback.to_string();
+        if !paths_to_scan.contains(&p) {
+            paths_to_scan.push(p);
+        }
+    }
+
+    // 3. User directories that may not be on PATH in the GUI session
+    for extra in &config.extra_paths {
+        let p = normalize_dir(&files::expand_tilde(extra).to_string_lossy());
+        if !p.is_empty() && !paths_to_scan.contains(&p) {
+            paths_to_scan.push(p);
+        }
+    }
+
+    // 4. Drop noisy directories
+    let excluded: Vec<PathBuf> = config.exclude_paths.iter().map(|p| files::expand_tilde(p)).collect();
+    paths_to_scan.retain(|p| !excluded.iter().any(|e| Path::new(p) == e));
+
+    paths_to_scan
+}
+
+/// `dir` without trailing slashes, so `/usr/bin/` and `/usr/bin` count as the same directory.
+fn normalize_dir(dir: &str) -> String {
+    match dir.trim_end_matches('/') {
+        "" if dir.starts_with('/') => "/".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Scans `dirs` for binaries (Permissive Mode unless `strict_exec`), handing
+/// `batch` the not-yet-seen names of each directory. Stops early once `batch`
+/// returns false, e.g. because nobody is listening anymore.
+pub fn scan_path(dirs: &[String], strict_exec: bool, full_paths: bool, use_cache: bool, mut batch: impl FnMut(Vec<Candidate>) -> bool) {
+    // Only for membership: which binary wins is decided by the order of `dirs`, like PATH lookup
+    let mut binaries = HashSet::new();
+    // A directory reached twice (`/bin` -> `/usr/bin`) would only shadow itself
+    let mut real_dirs = HashSet::new();
+
+    // Flatpak/Snap launchers the desktop entry scan won't cover get their app's name
+    let listed = desktop::application_dirs();
+    let labels: Vec<(&Path, Labels)> = dirs.iter()
+        .map(Path::new)
+        .filter_map(|dir| Labels::for_dir(dir, &listed).map(|labels| (dir, labels)))
+        .collect();
+    let exported = |dir: Option<&Path>, name: &str| labels.iter()
+        .filter(|(bin, _)| dir.map_or_else(|| bin.join(name).exists(), |dir| dir == *bin))
+        .find_map(|(bin, labels)| Some(Candidate::exported(labels.name(name)?, bin.join(name))));
+
+    // Reuse the last scan if none of the directories changed since
+    if use_cache {
+        if let Some(names) = cache::load(dirs, strict_exec) {
+            log::debug!("using {} cached binaries for {:?}", names.len(), dirs);
+            batch(names.into_iter().map(|name| exported(None, &name).unwrap_or_else(|| Candidate::new(name))).collect());
+            return;
+        }
+    }
+
+    log::debug!("scanning {:?} (strict_exec = {})", dirs, strict_exec);
+    for path_str in dirs {
+        let path = Path::new(path_str);
+
+        if !path.exists() {
+            log::debug!("skipping missing directory {}", path_str);
+            continue;
+        }
+        if !real_dirs.insert(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())) {
+            log::debug!("skipping {}, already scanned", path_str);
+            continue;
+        }
+
+        let mut found = Vec::new();
+
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                // Skip hidden files
+                if name.starts_with('.') { continue; }
+
+                if !is_launchable(&entry, strict_exec) { continue; }
+
+                let shadowed = !binaries.insert(name.clone());
+                if shadowed {
+                    log::debug!("{} is shadowed by an earlier directory", entry.path().display());
+                }
+
+                if full_paths {
+                    found.push(Candidate::at_path(name, entry.path(), shadowed));
+                } else if !shadowed {
+                    found.push(exported(Some(path), &name).unwrap_or_else(|| Candidate::new(name)));
+                }
+            }
+        }
+
+        if !found.is_empty() && !batch(found) {
+            return;
+        }
+    }
+
+    log::debug!("found {} binaries", binaries.len());
+    if use_cache {
+        let mut names: Vec<String> = binaries.into_iter().collect();
+        names.sort();
+        cache::store(dirs, strict_exec, &names);
+    }
+}
+
+/// Decides whether a directory entry should be offered as a binary.
+fn is_launchable(entry: &fs::DirEntry, strict_exec: bool) -> bool {
+    if strict_exec {
+        // STRICT CHECK:
+        // fs::metadata follows symlinks, so firefox -> firefox.sh is judged by its target.
+        return files::is_executable(&entry.path());
+    }
+
+    // PERMISSIVE CHECK:
+    // If it is in a bin folder and not a directory, assume it is executable.
+    // This fixes issues where symlinks (like firefox -> ../lib/firefox/firefox.sh)
+    // were being ignored by strict metadata checks.
+    entry.file_type().is_ok_and(|file_type| !file_type.is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    fn launchable_names(dir: &Path, strict_exec: bool) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .flatten()
+            .filter(|entry| is_launchable(entry, strict_exec))
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn result_names(core: &Core) -> Vec<&str> {
+        core.filtered_executables.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    fn core_with(names: &[&str]) -> Core {
+        let mut core = Core::new(&Config::default());
+        core.all_executables = names.iter().map(|name| Candidate::new(name.to_string())).collect();
+        core
+    }
+
+    fn search(core: &mut Core, query: &str) {
+        core.search_query = query.to_string();
+        core.update_filter();
+    }
+
+    #[test]
+    fn completion_remembers_the_typed_query() {
+        let mut core = core_with(&["firefox", "firewall"]);
+        search(&mut core, "fire");
+
+        core.selected_index = 1;
+        assert!(core.complete_selection());
+        assert_eq!(core.search_query, "firewall");
+
+        assert!(core.restore_typed_query());
+        assert_eq!(core.search_query, "fire");
+        assert_eq!(result_names(&core), ["firefox", "firewall"]);
+        assert!(!core.restore_typed_query());
+    }
+
+    #[test]
+    fn acronyms_rank_above_substring_hits() {
+        let mut core = core_with(&["devscan", "visual-studio-code", "vscode", "xdg-open", "libre-office"]);
+
+        search(&mut core, "vsc");
+        assert_eq!(result_names(&core), ["vscode", "visual-studio-code", "devscan"]);
+
+        search(&mut core, "xo");
+        assert_eq!(result_names(&core), ["xdg-open"]);
+
+        search(&mut core, "lo");
+        assert_eq!(result_names(&core), ["libre-office"]);
+
+        assert_eq!(initials("LibreOffice"), "lo");
+        assert_eq!(initials("org.gnome.Nautilus"), "ogn");
+        assert_eq!(initials("VSCode"), "vc");
+    }
+
+    #[test]
+    fn sudo_is_detected_in_any_case_and_spacing() {
+        for (query, command) in [("  sudo apt update", "apt update"), ("SUDO reboot", "reboot"), ("sudo   nano", "nano")] {
+            let mut core = core_with(&["apt", "nano", "reboot"]);
+            search(&mut core, query);
+
+            match core.resolve() {
+                Action::Sudo(resolved) => assert_eq!(resolved, command),
+                other => panic!("{} resolved to {:?}", query, other),
+            }
+        }
+    }
+
+    #[test]
+    fn sudo_queries_filter_on_the_command_after_it() {
+        let mut core = core_with(&["apt", "nano", "sudo", "sudoedit"]);
+
+        search(&mut core, "sudo na");
+        assert_eq!(result_names(&core), ["nano"]);
+
+        // Until a command follows, `sudo` is just a name to search for
+        search(&mut core, "sudo ");
+        assert_eq!(result_names(&core), ["sudo", "sudoedit"]);
+        search(&mut core, "  Sudo   a ");
+        assert_eq!(result_names(&core), ["apt", "nano"]);
+    }
+
+    #[test]
+    fn shrinking_results_clamp_the_highlight() {
+        let mut core = core_with(&[]);
+        core.merge(["vim", "bash", "awk", "nvim"].map(String::from).map(Candidate::new).to_vec());
+        assert_eq!(result_names(&core), ["awk", "bash", "nvim", "vim"]);
+
+        core.selected_index = 3;
+        search(&mut core, "vim");
+        assert_eq!(result_names(&core), ["vim", "nvim"]);
+        assert_eq!(core.selected_index, 1);
+
+        search(&mut core, "zzz");
+        assert_eq!(core.selected_index, 0);
+        assert!(core.filtered_executables.is_empty());
+    }
+
+    #[test]
+    fn resolves_prefixes_aliases_and_typed_arguments() {
+        let mut config = Config::default();
+        config.aliases.insert("ff".to_string(), "firefox --private-window".to_string());
+        let mut core = Core::new(&config);
+        core.merge(vec![Candidate::new("htop".to_string())]);
+
+        search(&mut core, "term: htop");
+        assert!(matches!(core.resolve(), Action::Launch { command, in_terminal: true } if command == "htop"));
+
+        search(&mut core, "ff example.org");
+        assert!(matches!(core.resolve(), Action::Launch { command, .. } if command == "firefox --private-window example.org"));
+
+        search(&mut core, "=6*7");
+        assert!(matches!(core.resolve(), Action::Copy(result) if result == "42"));
+        assert_eq!(core.launch_counts.get("htop"), Some(&1));
+    }
+
+    #[test]
+    fn confirm_patterns_hold_back_matching_commands() {
+        fn asks(core: &mut Core, query: &str) -> bool {
+            search(core, query);
+            let action = core.resolve();
+            core.needs_confirmation(&action)
+        }
+
+        let mut config = Config::default();
+        config.confirm.patterns = vec!["rm -rf".to_string(), "shutdown".to_string()];
+        let mut core = Core::new(&config);
+        core.merge(["htop", "shutdown"].map(|name| Candidate::new(name.to_string())).to_vec());
+
+        assert!(!asks(&mut core, "htop"));
+        assert!(asks(&mut core, "RM -RF ~/tmp"));
+        assert!(asks(&mut core, "sudo shutdown"));
+
+        let action = core.resolve();
+        core.ask_confirmation(action, false);
+        assert!(core.mode == AppMode::Confirm);
+        assert!(matches!(core.confirmed(), Some((Action::Sudo(command), false)) if command == "shutdown"));
+        assert!(core.mode == AppMode::Search && core.pending_action.is_none());
+
+        // Sudo itself only counts when asked to
+        assert!(!asks(&mut core, "sudo htop"));
+        core.confirm.sudo = true;
+        assert!(asks(&mut core, "sudo htop"));
+    }
+
+    #[test]
+    fn enter_policy_picks_the_highlight_or_the_typed_text() {
+        let mut core = core_with(&["vi", "view", "vim"]);
+        let command = |core: &mut Core, query: &str, highlight: usize| {
+            search(core, query);
+            core.selected_index = highlight;
+            match core.resolve() {
+                Action::Launch { command, .. } => command,
+                other => panic!("{} resolved to {:?}", query, other),
+            }
+        };
+
+        // Typed "vi" with "vim" highlighted, typed "vie" with "view" highlighted
+        for (policy, vi, vie) in [
+            (EnterPolicy::Auto, "vim", "view"),
+            (EnterPolicy::Selection, "vim", "view"),
+            (EnterPolicy::Exact, "vi", "view"),
+            (EnterPolicy::Raw, "vi", "vie"),
+        ] {
+            core.enter = policy;
+            assert_eq!(command(&mut core, "vi", 2), vi);
+            assert_eq!(command(&mut core, "vie", 0), vie);
+            assert_eq!(command(&mut core, "vi notes.txt", 0), "vi notes.txt");
+        }
+
+        // Selection falls back to the typed text only when nothing matches
+        core.enter = EnterPolicy::Selection;
+        assert_eq!(command(&mut core, "vim notes.txt", 0), "vim notes.txt");
+    }
+
+    #[test]
+    fn merging_keeps_the_highlight_on_the_same_name() {
+        let mut core = core_with(&["bash", "htop"]);
+        core.update_filter();
+        core.select_next();
+
+        core.merge(vec![Candidate::new("awk".to_string()), Candidate::new("cat".to_string())]);
+        assert_eq!(result_names(&core), ["awk", "bash", "cat", "htop"]);
+        assert_eq!(core.selected_index, 3);
+    }
+
+    #[test]
+    fn selection_wraps_around() {
+        let mut core = core_with(&["a", "b", "c"]);
+        core.update_filter();
+
+        core.select_previous();
+        assert_eq!(core.selected_index, 2);
+        core.select_next();
+        assert_eq!(core.selected_index, 0);
+
+        search(&mut core, "nothing");
+        core.select_next();
+        assert_eq!(core.selected_index, 0);
+
+        core.wrap = false;
+        search(&mut core, "");
+        core.select_previous();
+        assert_eq!(core.selected_index, 0);
+        core.select_next();
+        core.select_next();
+        core.select_next();
+        assert_eq!(core.selected_index, 2);
+    }
+
+    #[test]
+    fn empty_query_lists_launched_entries_first() {
+        let mut core = core_with(&["2to3", "7z", "firefox", "htop"]);
+        core.launch_counts.insert("htop".to_string(), 1);
+        core.launch_counts.insert("firefox".to_string(), 3);
+
+        core.update_filter();
+        assert_eq!(result_names(&core), ["firefox", "htop", "2to3", "7z"]);
+
+        // Typed queries keep their usual ranking
+        search(&mut core, "t");
+        assert_eq!(result_names(&core), ["2to3", "htop"]);
+    }
+
+    #[test]
+    fn pinned_matches_come_first_in_their_own_order() {
+        let mut core = core_with(&["alacritty", "code", "firefox", "kitty", "vscode"]);
+        core.pinned = ["kitty", "code", "missing"].map(String::from).to_vec();
+        core.launch_counts.insert("firefox".to_string(), 2);
+
+        core.update_filter();
+        assert_eq!(result_names(&core), ["kitty", "code", "firefox", "alacritty", "vscode"]);
+
+        search(&mut core, "code");
+        assert_eq!(result_names(&core), ["code", "vscode"]);
+
+        search(&mut core, "fire");
+        assert_eq!(result_names(&core), ["firefox"]);
+    }
+
+    #[test]
+    fn recent_mode_lists_only_history_until_something_is_typed() {
+        let mut core = core_with(&["alacritty", "code", "firefox", "htop", "kitty"]);
+        core.pinned = vec!["kitty".to_string()];
+        core.recent = ["htop", "Gone App", "firefox"].map(String::from).to_vec();
+
+        core.update_filter();
+        assert_eq!(result_names(&core), ["htop", "firefox"]);
+
+        search(&mut core, "t");
+        assert_eq!(result_names(&core), ["kitty", "alacritty", "htop"]);
+    }
+
+    #[test]
+    fn earlier_command_lines_are_suggested_under_their_program() {
+        let mut core = core_with(&["htop", "mpv", "vlc"]);
+        core.invocations = ["mpv --loop song.ogg", "htop -d 5", "mpv --fs film.mkv", "rsync -a src dst"].map(String::from).to_vec();
+
+        core.update_filter();
+        assert_eq!(result_names(&core), ["htop", "htop -d 5", "mpv", "mpv --loop song.ogg", "mpv --fs film.mkv", "vlc"]);
+
+        search(&mut core, "mp");
+        assert_eq!(result_names(&core), ["mpv", "mpv --loop song.ogg", "mpv --fs film.mkv"]);
+        core.selected_index = 1;
+        assert_eq!(core.invocation().as_deref(), Some("mpv --loop song.ogg"));
+        assert!(matches!(core.resolve(), Action::Launch { command, .. } if command == "mpv --loop song.ogg"));
+
+        search(&mut core, "rsync -");
+        assert_eq!(result_names(&core), ["rsync -a src dst"]);
+
+        search(&mut core, "mpv --loop other.ogg");
+        assert_eq!(core.invocation().as_deref(), Some("mpv --loop other.ogg"));
+        search(&mut core, "mpv");
+        assert_eq!(core.invocation(), None);
+        search(&mut core, "= 1 + 1");
+        assert_eq!(core.invocation(), None);
+    }
+
+    #[test]
+    fn sudo_toggle_applies_to_the_selection_until_the_query_changes() {
+        let mut core = core_with(&["htop", "iotop"]);
+        core.all_executables.push(Candidate::alias("top", "htop -d 5"));
+        core.aliases.insert("top".to_string(), "htop -d 5".to_string());
+        search(&mut core, "top");
+
+        assert_eq!(result_names(&core), ["top", "htop", "iotop"]);
+
+        core.toggle_sudo();
+        core.selected_index = 2;
+        assert!(matches!(core.resolve(), Action::Sudo(command) if command == "iotop"));
+        core.selected_index = 0;
+        assert!(matches!(core.resolve(), Action::Sudo(command) if command == "htop -d 5"));
+
+        core.set_query("io".to_string());
+        assert!(!core.sudo_selection);
+        assert!(matches!(core.resolve(), Action::Launch { command, .. } if command == "iotop"));
+    }
+
+    #[test]
+    fn marked_entries_launch_together_in_marking_order() {
+        let mut core = core_with(&["htop", "kitty", "nano"]);
+        core.all_executables.push(Candidate::alias("ff", "firefox --private-window"));
+        core.aliases.insert("ff".to_string(), "firefox --private-window".to_string());
+
+        for query in ["kitty", "ff", "htop", "kitty"] {
+            core.set_query(query.to_string());
+            core.toggle_mark();
+        }
+        assert_eq!(core.marked, ["ff", "htop"]);
+
+        // Prefix results can't be marked
+        core.set_query("=1+1".to_string());
+        core.toggle_mark();
+        assert_eq!(core.marked.len(), 2);
+
+        let Action::Batch(launches) = core.resolve() else { panic!("not a batch") };
+        let commands: Vec<_> = launches.iter().map(|action| match action {
+            Action::Launch { command, .. } => command.as_str(),
+            other => panic!("{:?}", other),
+        }).collect();
+        assert_eq!(commands, ["firefox --private-window", "htop"]);
+        assert_eq!(core.launch_counts.get("htop"), Some(&1));
+    }
+
+    #[test]
+    fn blocklist_and_allowlist_limit_what_is_listed_and_run() {
+        assert!(glob_match("gsd-*", "gsd-power") && glob_match("*", "") && glob_match("a*b*c", "abbc"));
+        assert!(!glob_match("gsd-*", "gsd") && !glob_match("a*a", "a") && !glob_match("[", "which"));
+
+        let mut core = core_with(&[]);
+        core.blocklist = vec!["[".to_string(), "gsd-*".to_string()];
+        core.merge(["[", "gsd-power", "htop", "nano"].map(|name| Candidate::new(name.to_string())).to_vec());
+        assert_eq!(result_names(&core), ["htop", "nano"]);
+
+        // Typed commands still run when only blocking
+        search(&mut core, "gsd-power");
+        assert!(matches!(core.resolve(), Action::Launch { command, .. } if command == "gsd-power"));
+
+        core.allowlist = vec!["h*".to_string()];
+        core.all_executables.clear();
+        core.merge(["htop", "nano"].map(|name| Candidate::new(name.to_string())).to_vec());
+        search(&mut core, "");
+        assert_eq!(result_names(&core), ["htop"]);
+
+        search(&mut core, "htop -d 5");
+        assert!(matches!(core.resolve(), Action::Launch { command, .. } if command == "htop -d 5"));
+        for blocked in ["nano", "sudo nano", "htop; nano", "gsd-power"] {
+            search(&mut core, blocked);
+            core.enter = EnterPolicy::Raw;
+            assert!(matches!(core.resolve(), Action::Error(_)), "{} ran", blocked);
+        }
+    }
+
+    #[test]
+    fn full_paths_keep_shadowed_binaries_apart() {
+        let root = env::temp_dir().join(format!("deemenu-fullpaths-test-{}", std::process::id()));
+        let (first, second) = (root.join("first"), root.join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        fs::write(first.join("python"), "").unwrap();
+        fs::write(second.join("python"), "").unwrap();
+        symlink(&first, root.join("alias")).unwrap();
+
+        let dirs: Vec<String> = ["first", "alias", "second"].iter().map(|d| root.join(d).to_string_lossy().to_string()).collect();
+        let mut found = Vec::new();
+        scan_path(&dirs, false, true, false, |batch| {
+            found.extend(batch);
+            true
+        });
+
+        let names: Vec<&str> = found.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["python".to_string(), format!("python ({})", second.display())]);
+        assert_eq!(found[0].command(), shell::quote(&first.join("python").to_string_lossy()));
+
+        // The earlier directory wins, whichever it is
+        let mut found = Vec::new();
+        scan_path(&[dirs[2].clone(), dirs[1].clone()], false, true, false, |batch| {
+            found.extend(batch);
+            true
+        });
+        assert_eq!(found[0].command(), shell::quote(&second.join("python").to_string_lossy()));
+        assert_eq!(found[1].name, format!("python ({})", root.join("alias").display()));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn quick_pick_counts_from_the_visible_page() {
+        let mut core = core_with(&["a", "b", "c"]);
+        core.update_filter();
+
+        assert_eq!(core.quick_pick(1, 2), Some(0));
+        assert_eq!(core.quick_pick(3, 2), None);
+
+        core.selected_index = 2;
+        assert_eq!(core.quick_pick(1, 2), Some(2));
+        assert_eq!(core.quick_pick(2, 2), None);
+    }
+
+    #[test]
+    fn aliases_are_listed_and_found_by_their_command() {
+        let mut config = Config::default();
+        config.aliases.insert("ff".to_string(), "firefox --private-window".to_string());
+        let mut core = Core::new(&config);
+
+        search(&mut core, "ff");
+        assert_eq!(result_names(&core), ["ff"]);
+
+        search(&mut core, "private");
+        assert_eq!(result_names(&core), ["ff"]);
+    }
+
+    #[test]
+    fn name_matches_rank_above_metadata_matches() {
+        let mut core = core_with(&["browser-sync"]);
+        core.all_executables.push(Candidate::from_desktop(&desktop::DesktopEntry {
+            name: "Firefox".to_string(),
+            keywords: vec!["Internet".to_string(), "Browser".to_string()],
+            exec: "firefox %u".to_string(),
+            ..Default::default()
+        }, None));
+
+        search(&mut core, "browser");
+        assert_eq!(result_names(&core), ["browser-sync", "Firefox"]);
+        assert_eq!(core.filtered_executables[1].command(), "firefox");
+    }
+
+    #[test]
+    fn category_queries_only_list_desktop_entries_in_it() {
+        let mut core = core_with(&["firewall"]);
+        for (name, categories) in [("Firefox", ["Network", "WebBrowser"]), ("Files", ["System", "FileManager"])] {
+            core.all_executables.push(Candidate::from_desktop(&desktop::DesktopEntry {
+                name: name.to_string(),
+                categories: categories.map(String::from).to_vec(),
+                exec: name.to_lowercase(),
+                ..Default::default()
+            }, None));
+        }
+
+        search(&mut core, "@network");
+        assert_eq!(result_names(&core), ["Firefox"]);
+        search(&mut core, "@Web fire");
+        assert_eq!(result_names(&core), ["Firefox"]);
+        search(&mut core, "@system fire");
+        assert!(core.filtered_executables.is_empty());
+        search(&mut core, "fi");
+        assert_eq!(result_names(&core), ["firewall", "Firefox", "Files"]);
+
+        search(&mut core, "@net fire");
+        assert!(matches!(core.resolve(), Action::Launch { command, .. } if command == "firefox"));
+    }
+
+    #[test]
+    fn open_with_lists_the_apps_for_the_file_type() {
+        let mut core = core_with(&["feh"]);
+        for (name, mime_types) in [("Image Viewer", "image/*"), ("Text Editor", "text/plain"), ("GIMP", "image/png")] {
+            core.all_executables.push(Candidate::from_desktop(&desktop::DesktopEntry {
+                name: name.to_string(),
+                mime_types: vec![mime_types.to_string()],
+                exec: format!("{} %U", name.to_lowercase().replace(' ', "-")),
+                ..Default::default()
+            }, None));
+        }
+
+        let file = env::temp_dir().join(format!("deemenu-open-with-{}.png", std::process::id()));
+        fs::write(&file, b"").unwrap();
+        search(&mut core, &file.to_string_lossy());
+        assert_eq!(core.selected_file(), Some(file.clone()));
+        search(&mut core, "/");
+        assert_eq!(core.selected_file(), None);
+        search(&mut core, "/bin/sh -c true");
+        assert_eq!(core.selected_file(), None);
+
+        let spaced = env::temp_dir().join(format!("deemenu open with {}", std::process::id()));
+        fs::create_dir_all(&spaced).unwrap();
+        fs::write(spaced.join("photo.png"), b"").unwrap();
+        search(&mut core, &format!("{}/pho", spaced.display()));
+        assert_eq!(core.selected_file(), Some(spaced.join("photo.png")));
+        fs::remove_dir_all(&spaced).unwrap();
+
+        search(&mut core, &file.to_string_lossy());
+        core.start_open_with(file.clone(), "image/png".to_string());
+        assert_eq!(core.search_query, "");
+        assert_eq!(result_names(&core), ["Image Viewer", "GIMP"]);
+        search(&mut core, "gi");
+        let Action::Launch { command, .. } = core.resolve() else { panic!("expected a launch") };
+        assert_eq!(command, format!("gimp '{}'", file.display()));
+
+        assert!(core.cancel_open_with());
+        assert_eq!(core.search_query, file.to_string_lossy());
+        assert!(!core.cancel_open_with());
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn calculations_are_kept_for_the_next_one() {
+        let mut core = core_with(&["htop"]);
+        search(&mut core, "htop");
+        assert_eq!(core.keep_calculation(), None);
+
+        search(&mut core, "= 3*4");
+        assert_eq!(core.keep_calculation().as_deref(), Some("12"));
+        assert_eq!(core.search_query, "=");
+        search(&mut core, "=10 mi to km");
+        assert_eq!(core.keep_calculation().as_deref(), Some("16.09344"));
+        search(&mut core, "=1/0");
+        assert_eq!(core.keep_calculation(), None);
+        assert_eq!(core.calculations, [("3*4".to_string(), "12".to_string()), ("10 mi to km".to_string(), "16.09344 km".to_string())]);
+
+        for _ in 0..MAX_CALCULATIONS {
+            search(&mut core, "=1+1");
+            core.keep_calculation();
+        }
+        assert_eq!(core.calculations.len(), MAX_CALCULATIONS);
+    }
+
+    #[test]
+    fn dmenu_lines_show_and_print_the_chosen_fields() {
+        let fields = Fields { delimiter: Some("\t".to_string()), with_nth: Some(2), accept_nth: Some(1) };
+        let choice = Candidate::choice("wlan0\tHome Wi-Fi", &fields);
+        assert_eq!((choice.name.as_str(), choice.command()), ("Home Wi-Fi", "wlan0"));
+
+        let fields = Fields { with_nth: Some(2), ..Default::default() };
+        let choice = Candidate::choice("42  firefox --new-window", &fields);
+        assert_eq!((choice.name.as_str(), choice.command()), ("firefox", "42  firefox --new-window"));
+
+        let missing = Candidate::choice("lonely", &fields);
+        assert_eq!((missing.name.as_str(), missing.command()), ("lonely", "lonely"));
+
+        let mut core = core_with(&[]);
+        core.use_choices(vec![Candidate::choice("1 one", &Fields { with_nth: Some(2), accept_nth: Some(1), ..Default::default() })]);
+        search(&mut core, "on");
+        assert_eq!(core.dmenu_choice(false), Some("1"));
+        assert_eq!(core.dmenu_choice(true), Some("on"));
+    }
+
+    #[test]
+    fn records_matched_range_for_highlighting() {
+        let mut core = core_with(&["VSCode", "code"]);
+        core.all_executables.push(Candidate::from_desktop(&desktop::DesktopEntry {
+            name: "Editor".to_string(),
+            keywords: vec!["code".to_string()],
+            ..Default::default()
+        }, None));
+
+        search(&mut core, "cod");
+        let ranges: Vec<_> = core.filtered_executables.iter().map(|c| c.matched.clone()).collect();
+        assert_eq!(ranges, [Some(2..5), Some(0..3), None]);
+    }
+
+    #[test]
+    fn keeps_every_match_beyond_the_render_page() {
+        let names: Vec<String> = (0..120).map(|i| format!("tool-{:03}", i)).collect();
+        let mut core = core_with(&names.iter().map(String::as_str).collect::<Vec<_>>());
+
+        search(&mut core, "tool");
+        assert_eq!(core.filtered_executables.len(), 120);
+
+        search(&mut core, "");
+        assert_eq!(result_names(&core).last(), Some(&"tool-119"));
+    }
+
+    #[test]
+    fn filter_uses_cached_lowercase_names() {
+        let mut names: Vec<String> = (0..50_000).map(|i| format!("tool-{:05}", i)).collect();
+        names.push("Zenity".to_string());
+        let mut core = core_with(&names.iter().map(String::as_str).collect::<Vec<_>>());
+
+        // Matching must go through the cached `lower` field, never re-lowercasing `name`
+        Arc::get_mut(&mut core.all_executables.last_mut().unwrap().entry).unwrap().lower = "cached-only".to_string();
+
+        search(&mut core, "cached");
+        assert_eq!(result_names(&core), ["Zenity"]);
+
+        search(&mut core, "tool-4999");
+        assert_eq!(core.filtered_executables.len(), 10);
+    }
+
+    #[test]
+    fn accents_are_optional_in_queries() {
+        let mut core = core_with(&["Café", "Évolution", "evince", "Straße"]);
+        core.all_executables.push(Candidate::from_desktop(&desktop::DesktopEntry {
+            name: "Files".to_string(),
+            keywords: vec!["Gestionnaire de fichiers".to_string(), "Dateiverwaltung".to_string(), "Répertoire".to_string()],
+            exec: "nautilus".to_string(),
+            ..Default::default()
+        }, None));
+
+        search(&mut core, "evolution");
+        assert_eq!(result_names(&core), ["Évolution"]);
+        search(&mut core, "cafe");
+        assert_eq!(result_names(&core), ["Café"]);
+        search(&mut core, "café");
+        assert_eq!(result_names(&core), ["Café"]);
+        search(&mut core, "strasse");
+        assert_eq!(result_names(&core), ["Straße"]);
+        search(&mut core, "repertoire");
+        assert_eq!(result_names(&core), ["Files"]);
+
+        // Decomposed input (e + combining acute) folds the same way
+        assert_eq!(fold("E\u{301}volution"), "evolution");
+        // Beyond Latin-1: Vietnamese, caron and comma-below letters
+        assert_eq!(fold("Trạng Thế ǎ Școală Łódź"), "trang the a scoala lodz");
+        assert_eq!(initials("Éditeur de Texte"), "edt");
+    }
+
+    #[test]
+    fn smart_case_matches_uppercase_queries_exactly() {
+        let mut core = core_with(&["Code", "code", "vscode"]);
+
+        search(&mut core, "code");
+        assert_eq!(result_names(&core), ["Code", "code", "vscode"]);
+
+        search(&mut core, "Code");
+        assert_eq!(result_names(&core), ["Code"]);
+    }
+
+    #[test]
+    fn non_latin_queries_filter_like_any_other() {
+        let mut core = core_with(&["ファイル", "文件管理器", "Терминал", "терминал-2"]);
+
+        search(&mut core, "管理");
+        assert_eq!(result_names(&core), ["文件管理器"]);
+        assert_eq!(core.filtered_executables[0].matched, Some(6..12));
+
+        search(&mut core, "ファ");
+        assert_eq!(result_names(&core), ["ファイル"]);
+
+        // Smart case works on Cyrillic capitals too
+        search(&mut core, "терм");
+        assert_eq!(result_names(&core), ["Терминал", "терминал-2"]);
+        search(&mut core, "Терм");
+        assert_eq!(result_names(&core), ["Терминал"]);
+    }
+
+    #[test]
+    fn case_mode_can_be_forced() {
+        let mut core = core_with(&["Code", "code"]);
+
+        core.case = CaseMode::Insensitive;
+        search(&mut core, "CODE");
+        assert_eq!(result_names(&core), ["Code", "code"]);
+
+        core.case = CaseMode::Sensitive;
+        search(&mut core, "code");
+        assert_eq!(result_names(&core), ["code"]);
+    }
+
+    #[test]
+    fn scan_dirs_merges_extra_and_drops_excluded_paths() {
+        let config = Config {
+            extra_paths: vec!["~/bin".to_string(), "/usr/bin".to_string()],
+            exclude_paths: vec!["/sbin/".to_string()],
+            ..Config::default()
+        };
+        let home = env::var("HOME").unwrap();
+
+        let dirs = scan_dirs(&config);
+        // "/usr/bin" is already a standard directory, so "~/bin" stays last
+        assert_eq!(dirs.last(), Some(&format!("{}/bin", home)));
+        assert!(!dirs.iter().any(|d| d == "/sbin"));
+    }
+
+    #[test]
+    fn scan_dirs_survive_empty_path_and_trailing_slashes() {
+        let config = Config::default();
+
+        let dirs = dirs_to_scan("", &config);
+        assert_eq!(dirs[..3], ["/usr/bin", "/usr/local/bin", "/bin"]);
+
+        let dirs = dirs_to_scan("/usr/bin/:/opt/tools//::/usr/bin", &config);
+        assert_eq!(dirs[..3], ["/usr/bin", "/opt/tools", "/usr/local/bin"]);
+        assert_eq!(dirs.iter().filter(|d| *d == "/usr/bin").count(), 1);
+        assert_eq!(normalize_dir("/"), "/");
+    }
+
+    #[test]
+    fn strict_exec_requires_execute_bit() {
+        let dir = env::temp_dir().join(format!("deemenu-strict-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("subdir")).unwrap();
+
+        fs::write(dir.join("tool"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(dir.join("tool"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("README"), "docs").unwrap();
+        fs::set_permissions(dir.join("README"), fs::Permissions::from_mode(0o644)).unwrap();
+        symlink(dir.join("tool"), dir.join("tool-link")).unwrap();
+
+        assert_eq!(launchable_names(&dir, false), ["README", "tool", "tool-link"]);
+        assert_eq!(launchable_names(&dir, true), ["tool", "tool-link"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
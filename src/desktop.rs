@@ -0,0 +1,412 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{cache, files};
+
+/// The parts of a freedesktop `.desktop` file DeeMenu cares about.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DesktopEntry {
+    pub name: String,
+    pub generic_name: Option<String>,
+    pub comment: Option<String>,
+    pub keywords: Vec<String>,
+    /// `Categories`, e.g. `Network` and `WebBrowser`
+    pub categories: Vec<String>,
+    /// `MimeType`: the file types it opens, e.g. `image/png` or `image/*`
+    pub mime_types: Vec<String>,
+    pub exec: String,
+    /// `Icon`: a theme icon name (`firefox`) or an absolute path
+    pub icon: Option<String>,
+    pub terminal: bool,
+    /// `NoDisplay=true`: still valid (e.g. for the Terminal flag), but not listed
+    pub no_display: bool,
+    /// `TryExec`: a binary that must be installed for the entry to count
+    pub try_exec: Option<String>,
+}
+
+impl DesktopEntry {
+    /// Basename of the binary the `Exec` line starts with (e.g. `htop` for `Exec=/usr/bin/htop %F`)
+    pub fn binary(&self) -> Option<String> {
+        let first = self.exec.split_whitespace().next()?;
+        let first = first.trim_matches('"');
+        Path::new(first)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+    }
+
+    /// Whether the `TryExec` binary, if any, exists; leftovers of uninstalled programs say which one they need.
+    pub fn is_installed(&self) -> bool {
+        self.try_exec.as_deref().is_none_or(is_installed)
+    }
+
+    /// The `Exec` line with field codes (`%U`, `%f`, ...) removed, ready to run.
+    pub fn command(&self) -> String {
+        self.exec
+            .split_whitespace()
+            .filter(|arg| !(arg.len() == 2 && arg.starts_with('%') && arg != &"%%"))
+            .map(|arg| arg.replace("%%", "%"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// `applications` under `$XDG_DATA_HOME` and each of `$XDG_DATA_DIRS`, most important first.
+pub fn application_dirs() -> Vec<PathBuf> {
+    let data_home = match env::var_os("XDG_DATA_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")),
+    };
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "/usr/share:/usr/local/share".to_string());
+
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').filter(|d| !d.is_empty()).map(PathBuf::from))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// The locale of messages: `$LC_ALL`, `$LC_MESSAGES` or `$LANG`, whichever is set first.
+/// `None` for the untranslated `C`/`POSIX` locale.
+fn current_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|v| !v.is_empty()))
+        .filter(|locale| locale != "C" && locale != "POSIX")
+}
+
+/// The `[locale]` suffixes to look for, best first, as the desktop entry spec
+/// matches `lang_COUNTRY.ENCODING@MODIFIER`: `lang_COUNTRY@MODIFIER`,
+/// `lang_COUNTRY`, `lang@MODIFIER`, then `lang`. The encoding never counts.
+fn locale_keys(locale: &str) -> Vec<String> {
+    let (rest, modifier) = match locale.split_once('@') {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale, None),
+    };
+    let rest = rest.split('.').next().unwrap_or(rest);
+    let (lang, country) = match rest.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (rest, None),
+    };
+
+    let mut keys = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        keys.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        keys.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        keys.push(format!("{}@{}", lang, modifier));
+    }
+    keys.push(lang.to_string());
+    keys
+}
+
+/// The `locale_keys` of the current locale, or none when it is untranslated.
+pub fn message_locales() -> Vec<String> {
+    current_locale().map(|locale| locale_keys(&locale)).unwrap_or_default()
+}
+
+/// Reads every application entry that hasn't been deleted with `Hidden=true`,
+/// with names and descriptions in the current locale where translated.
+/// With `use_cache`, the last parse is reused while no entry file has changed.
+pub fn scan(use_cache: bool) -> Vec<DesktopEntry> {
+    let dirs = application_dirs();
+    let locales = message_locales();
+
+    // Taken before parsing, so a file changing meanwhile invalidates the cache next time
+    let stamp = use_cache.then(|| stamp(&dirs, &locales));
+    let entries = match stamp.as_deref().and_then(cache::load_desktop) {
+        Some(entries) => {
+            log::debug!("using {} cached desktop entries", entries.len());
+            entries
+        }
+        None => {
+            let entries = scan_dirs(&dirs, &locales);
+            if let Some(stamp) = &stamp {
+                cache::store_desktop(stamp, &entries);
+            }
+            entries
+        }
+    };
+
+    // Installing a TryExec binary doesn't touch the entry, so this isn't cached
+    entries.into_iter().filter(DesktopEntry::is_installed).collect()
+}
+
+/// What the entries in `dirs` are read from: the locales, then the mtime of
+/// every entry file, so adding, removing or editing one invalidates the cache.
+fn stamp(dirs: &[PathBuf], locales: &[String]) -> String {
+    let mut files = Vec::new();
+    for dir in dirs {
+        collect_files(dir, &mut files);
+    }
+
+    let mut lines: Vec<String> = files.iter().map(|path| format!("{}\t{}", cache::mtime(path), path.display())).collect();
+    lines.sort();
+    format!("locales={}\n{}", locales.join(";"), lines.join("\n"))
+}
+
+/// Entries from `dirs`, where an earlier directory's file shadows any later
+/// one with the same desktop-file ID (even when it hides the entry).
+fn scan_dirs(dirs: &[PathBuf], locales: &[String]) -> Vec<DesktopEntry> {
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+
+    for dir in dirs {
+        let mut files = Vec::new();
+        collect_files(dir, &mut files);
+
+        for path in files {
+            let Some(id) = file_id(dir, &path) else { continue };
+            if !seen.insert(id) { continue; }
+
+            if let Some(entry) = read(&path, locales) {
+                entries.push(entry);
+            }
+        }
+    }
+
+    entries
+}
+
+/// `.desktop` files in `dir` and its subdirectories.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    collect_files_once(dir, files, &mut HashSet::new());
+}
+
+/// `collect_files`, entering each directory once so symlink loops (`self -> .`) end.
+fn collect_files_once(dir: &Path, files: &mut Vec<PathBuf>, visited: &mut HashSet<PathBuf>) {
+    let Ok(canonical) = fs::canonicalize(dir) else { return };
+    if !visited.insert(canonical) {
+        return;
+    }
+    let Ok(read) = fs::read_dir(dir) else { return };
+
+    for file in read.flatten() {
+        let path = file.path();
+        if path.is_dir() {
+            collect_files_once(&path, files, visited);
+        } else if path.extension().is_some_and(|ext| ext == "desktop") {
+            files.push(path);
+        }
+    }
+}
+
+/// The desktop-file ID: the path below `applications` with `/` turned into `-`,
+/// so `kde4/konsole.desktop` is `kde4-konsole.desktop`.
+fn file_id(dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(dir).ok()?;
+    Some(relative.to_string_lossy().replace('/', "-"))
+}
+
+/// Whether a `TryExec` binary exists and is executable; bare names are looked up in PATH.
+fn is_installed(binary: &str) -> bool {
+    if binary.contains('/') {
+        return files::is_executable(Path::new(binary));
+    }
+    env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|dir| files::is_executable(&dir.join(binary))))
+}
+
+/// Keeps `value` in `slot` unless it already holds one for a better locale.
+/// `rank` is the position in the `locales` list, past its end for untranslated values.
+fn offer<T>(slot: &mut Option<(usize, T)>, rank: usize, value: T) {
+    if slot.as_ref().is_none_or(|(best, _)| rank <= *best) {
+        *slot = Some((rank, value));
+    }
+}
+
+/// Parses an application entry, taking translated `Name[de]=`-style values
+/// for the first of `locales` (see `locale_keys`) that has one.
+pub fn parse(path: &Path, locales: &[String]) -> Option<DesktopEntry> {
+    read(path, locales).filter(DesktopEntry::is_installed)
+}
+
+/// `parse`, keeping entries whose `TryExec` binary is missing.
+fn read(path: &Path, locales: &[String]) -> Option<DesktopEntry> {
+    let contents = fs::read_to_string(path).ok()?;
+
+    let mut in_main_group = false;
+    let mut name = None;
+    let mut generic_name = None;
+    let mut comment = None;
+    let mut keywords = None;
+    let mut categories = Vec::new();
+    let mut mime_types = Vec::new();
+    let mut exec = None;
+    let mut try_exec = None;
+    let mut icon = None;
+    let mut terminal = false;
+    let mut no_display = false;
+    let mut hidden = false;
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+
+        // Only [Desktop Entry] matters; [Desktop Action ...] groups carry their own Exec lines
+        if line.starts_with('[') {
+            in_main_group = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_main_group { continue; }
+
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let (key, rank) = match key.trim().split_once('[') {
+            Some((key, locale)) => {
+                let locale = locale.strip_suffix(']').unwrap_or(locale);
+                match locales.iter().position(|l| l == locale) {
+                    Some(rank) => (key, rank),
+                    // A translation for some other language
+                    None => continue,
+                }
+            }
+            None => (key.trim(), locales.len()),
+        };
+        match key {
+            "Name" => offer(&mut name, rank, value.trim().to_string()),
+            "GenericName" => offer(&mut generic_name, rank, value.trim().to_string()),
+            "Comment" => offer(&mut comment, rank, value.trim().to_string()),
+            "Keywords" => {
+                offer(&mut keywords, rank, value.split(';').map(str::trim).filter(|k| !k.is_empty()).map(String::from).collect());
+            }
+            _ if rank < locales.len() => {}
+            "Exec" => exec = Some(value.trim().to_string()),
+            "Categories" => categories = value.split(';').map(str::trim).filter(|c| !c.is_empty()).map(String::from).collect(),
+            "MimeType" => mime_types = value.split(';').map(str::trim).filter(|m| !m.is_empty()).map(String::from).collect(),
+            "TryExec" => try_exec = Some(value.trim().to_string()).filter(|t| !t.is_empty()),
+            "Icon" => icon = Some(value.trim().to_string()).filter(|i| !i.is_empty()),
+            "NoDisplay" => no_display = value.trim() == "true",
+            "Terminal" => terminal = value.trim() == "true",
+            "Hidden" => hidden = value.trim() == "true",
+            "Type" if value.trim() != "Application" => return None,
+            _ => {}
+        }
+    }
+
+    if hidden { return None; }
+
+    Some(DesktopEntry {
+        name: name?.1,
+        generic_name: generic_name.map(|(_, value)| value),
+        comment: comment.map(|(_, value)| value),
+        keywords: keywords.map(|(_, value)| value).unwrap_or_default(),
+        categories,
+        mime_types,
+        exec: exec?,
+        icon,
+        terminal,
+        no_display,
+        try_exec,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn earlier_directories_shadow_entries_with_the_same_id() {
+        let root = env::temp_dir().join(format!("deemenu-desktop-test-{}", std::process::id()));
+        let user = root.join("user/applications");
+        let system = root.join("system/applications");
+        fs::create_dir_all(&user).unwrap();
+        fs::create_dir_all(system.join("kde4")).unwrap();
+
+        let entry = |name: &str| format!("[Desktop Entry]\nType=Application\nName={}\nExec={}\n", name, name.to_lowercase());
+        fs::write(user.join("editor.desktop"), entry("My Editor")).unwrap();
+        fs::write(system.join("editor.desktop"), entry("Editor")).unwrap();
+        fs::write(user.join("games.desktop"), "[Desktop Entry]\nType=Application\nHidden=true\n").unwrap();
+        fs::write(system.join("games.desktop"), entry("Games")).unwrap();
+        fs::write(system.join("kde4/konsole.desktop"), entry("Konsole")).unwrap();
+        // A link back up must not send the scan round in circles
+        std::os::unix::fs::symlink("..", system.join("kde4/loop")).unwrap();
+
+        let mut names: Vec<String> = scan_dirs(&[user, system], &[]).into_iter().map(|e| e.name).collect();
+        names.sort();
+        assert_eq!(names, ["Konsole", "My Editor"]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn reads_categories_and_mime_types() {
+        let path = env::temp_dir().join(format!("deemenu-categories-test-{}.desktop", std::process::id()));
+        fs::write(&path, "[Desktop Entry]\nType=Application\nName=Firefox\nExec=firefox\nCategories=Network;WebBrowser;\nMimeType=text/html;image/*;\n").unwrap();
+
+        let entry = parse(&path, &[]).unwrap();
+        assert_eq!(entry.categories, ["Network", "WebBrowser"]);
+        assert_eq!(entry.mime_types, ["text/html", "image/*"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn stamp_changes_with_entry_files_and_locales() {
+        let dir = env::temp_dir().join(format!("deemenu-stamp-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("kde4")).unwrap();
+        fs::write(dir.join("a.desktop"), "").unwrap();
+        let dirs = [dir.clone()];
+
+        let before = stamp(&dirs, &[]);
+        assert_eq!(before, stamp(&dirs, &[]));
+        assert_ne!(before, stamp(&dirs, &["de".to_string()]));
+
+        fs::write(dir.join("kde4/b.desktop"), "").unwrap();
+        assert_ne!(before, stamp(&dirs, &[]));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn entries_whose_try_exec_is_missing_are_skipped() {
+        let dir = env::temp_dir().join(format!("deemenu-tryexec-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let entry = |name: &str, try_exec: &str| {
+            format!("[Desktop Entry]\nType=Application\nName={}\nExec=x\nTryExec={}\n", name, try_exec)
+        };
+        fs::write(dir.join("gone.desktop"), entry("Gone", "/nonexistent/deemenu-test-binary")).unwrap();
+        fs::write(dir.join("absolute.desktop"), entry("Absolute", "/bin/sh")).unwrap();
+        fs::write(dir.join("on-path.desktop"), entry("OnPath", "sh")).unwrap();
+
+        assert!(parse(&dir.join("gone.desktop"), &[]).is_none());
+        assert!(parse(&dir.join("absolute.desktop"), &[]).is_some());
+        assert!(parse(&dir.join("on-path.desktop"), &[]).is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn locale_fallback_follows_the_spec() {
+        assert_eq!(locale_keys("de_DE.UTF-8@euro"), ["de_DE@euro", "de_DE", "de@euro", "de"]);
+        assert_eq!(locale_keys("fr_CA.UTF-8"), ["fr_CA", "fr"]);
+        assert_eq!(locale_keys("sr@latin"), ["sr@latin", "sr"]);
+        assert_eq!(locale_keys("ja"), ["ja"]);
+    }
+
+    #[test]
+    fn picks_names_for_the_best_matching_locale() {
+        let path = env::temp_dir().join(format!("deemenu-locale-test-{}.desktop", std::process::id()));
+        fs::write(&path, "[Desktop Entry]\nType=Application\nName[de_DE]=Dateien\nName=Files\n\
+            Name[de]=Dateimanager\nName[fr]=Fichiers\nComment=Browse files\nComment[de]=Dateien durchsuchen\n\
+            Keywords=folder;\nKeywords[fr]=dossier;\nExec[de]=wrong\nExec=nautilus\n").unwrap();
+
+        let entry = |locale: &str| parse(&path, &locale_keys(locale)).unwrap();
+        assert_eq!(entry("de_DE.UTF-8").name, "Dateien");
+        assert_eq!(entry("de_AT").name, "Dateimanager");
+        assert_eq!(entry("de_AT").comment.as_deref(), Some("Dateien durchsuchen"));
+        assert_eq!(entry("de_AT").keywords, ["folder"]);
+        assert_eq!(entry("fr_FR").keywords, ["dossier"]);
+
+        let untranslated = parse(&path, &[]).unwrap();
+        assert_eq!((untranslated.name.as_str(), untranslated.exec.as_str()), ("Files", "nautilus"));
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,38 @@
+//! The emoji and symbol table behind the `:` prefix, one `<character>\t<name>`
+//! line each (names follow Unicode's, lowercased).
+
+const TABLE: &str = include_str!("emoji.txt");
+
+/// Characters whose name contains every word of `query`, as `(character, name)`.
+/// Names starting with the query come first; otherwise the table order is kept.
+pub fn search(query: &str) -> Vec<(&'static str, &'static str)> {
+    let query = query.trim().to_lowercase();
+    let words: Vec<&str> = query.split_whitespace().collect();
+
+    let mut found: Vec<(&str, &str)> = TABLE
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(_, name)| words.iter().all(|word| name.contains(word)))
+        .collect();
+
+    // Stable, so ties keep the table order
+    found.sort_by_key(|(_, name)| !name.starts_with(&query));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_word_must_match_and_prefixes_rank_first() {
+        let names = |query| search(query).into_iter().map(|(_, name)| name).collect::<Vec<_>>();
+
+        assert_eq!(search("red heart"), [("❤️", "red heart")]);
+        assert_eq!(names("Star"), ["star struck", "star", "glowing star"]);
+        assert!(names("heart").iter().all(|name| name.contains("heart")));
+        assert_eq!(names("rocket"), ["rocket"]);
+        assert!(search("no such emoji").is_empty());
+        assert!(TABLE.lines().all(|line| line.split_once('\t').is_some_and(|(c, name)| !c.is_empty() && !name.is_empty())));
+    }
+}
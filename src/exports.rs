@@ -0,0 +1,89 @@
+//! Friendly names for the launchers Flatpak and Snap put on PATH
+//! (`org.mozilla.firefox`, `code.insiders`), read from the desktop file
+//! exported along with each of them.
+
+use std::path::{Path, PathBuf};
+
+use crate::desktop;
+
+const SNAP_BIN: &str = "/snap/bin";
+const SNAP_APPLICATIONS: &str = "/var/lib/snapd/desktop/applications";
+
+/// Names for the binaries of one export directory.
+pub struct Labels {
+    applications: PathBuf,
+    snap: bool,
+    locales: Vec<String>,
+}
+
+impl Labels {
+    /// Labels for `bin_dir` if it is a Flatpak (`.../exports/bin`) or Snap
+    /// (`/snap/bin`) export directory whose desktop files aren't among the
+    /// `listed` application directories; those already show up as desktop entries.
+    pub fn for_dir(bin_dir: &Path, listed: &[PathBuf]) -> Option<Self> {
+        let snap = bin_dir == Path::new(SNAP_BIN);
+        let applications = if snap {
+            PathBuf::from(SNAP_APPLICATIONS)
+        } else if bin_dir.ends_with("exports/bin") {
+            bin_dir.parent()?.join("share/applications")
+        } else {
+            return None;
+        };
+
+        if !applications.is_dir() || listed.contains(&applications) {
+            return None;
+        }
+        Some(Self { applications, snap, locales: desktop::message_locales() })
+    }
+
+    /// The `Name` of the desktop entry exported for `binary`, if there is one.
+    pub fn name(&self, binary: &str) -> Option<String> {
+        let path = self.applications.join(desktop_file(binary, self.snap));
+        desktop::parse(&path, &self.locales).filter(|entry| !entry.no_display).map(|entry| entry.name)
+    }
+}
+
+/// The desktop file exported for a binary: Flatpak names it after the app ID,
+/// Snap after `<snap>_<app>` (`code.insiders` -> `code_insiders`, `code` -> `code_code`).
+fn desktop_file(binary: &str, snap: bool) -> String {
+    match binary.split_once('.') {
+        Some((snap_name, app)) if snap => format!("{}_{}.desktop", snap_name, app),
+        None if snap => format!("{}_{}.desktop", binary, binary),
+        _ => format!("{}.desktop", binary),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn snap_and_flatpak_desktop_files() {
+        assert_eq!(desktop_file("org.mozilla.firefox", false), "org.mozilla.firefox.desktop");
+        assert_eq!(desktop_file("code", true), "code_code.desktop");
+        assert_eq!(desktop_file("code.insiders", true), "code_insiders.desktop");
+    }
+
+    #[test]
+    fn names_flatpak_binaries_unless_their_entries_are_listed() {
+        let root = env::temp_dir().join(format!("deemenu-exports-test-{}", std::process::id()));
+        let bin = root.join("exports/bin");
+        let applications = root.join("exports/share/applications");
+        fs::create_dir_all(&bin).unwrap();
+        fs::create_dir_all(&applications).unwrap();
+        fs::write(
+            applications.join("org.mozilla.firefox.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Firefox\nExec=/usr/bin/flatpak run org.mozilla.firefox\n",
+        ).unwrap();
+
+        let labels = Labels::for_dir(&bin, &[]).unwrap();
+        assert_eq!(labels.name("org.mozilla.firefox").as_deref(), Some("Firefox"));
+        assert_eq!(labels.name("org.gnome.Missing"), None);
+
+        assert!(Labels::for_dir(&bin, &[applications]).is_none());
+        assert!(Labels::for_dir(&root, &[]).is_none());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Queries starting with `/` or `~/` complete filesystem paths instead of binaries.
+pub fn is_path_query(query: &str) -> bool {
+    query.starts_with('/') || query.starts_with("~/") || query == "~"
+}
+
+/// The MIME type of the file at `path` (e.g. `image/png`), asking `xdg-mime`, then `file`.
+pub fn mime_type(path: &Path) -> Option<String> {
+    let queries: [(&str, &[&str]); 2] = [("xdg-mime", &["query", "filetype"]), ("file", &["--brief", "--mime-type"])];
+    queries.iter().find_map(|(program, args)| {
+        let output = Command::new(program).args(*args).arg(path).stderr(Stdio::null()).output().ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // `text/plain; charset=us-ascii` from some fallbacks
+        let mime = stdout.split(';').next().unwrap_or_default().trim();
+        (output.status.success() && mime.contains('/')).then(|| mime.to_string())
+    })
+}
+
+/// Expands a leading `~` to `$HOME`.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Lists entries of the typed path's directory whose names start with the
+/// typed remainder, written the way the user typed them (keeping `~/`).
+/// Directories get a trailing `/` so completing them keeps descending.
+pub fn complete(query: &str) -> Vec<String> {
+    let query = if query == "~" { "~/" } else { query };
+
+    // "/usr/sh" -> dir "/usr/", leaf "sh"
+    let split = query.rfind('/').map_or(0, |i| i + 1);
+    let (dir, leaf) = query.split_at(split);
+
+    let Ok(entries) = fs::read_dir(expand_tilde(dir)) else { return Vec::new() };
+
+    let mut matches: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            // Dotfiles only once the user starts typing one
+            if !name.starts_with(leaf) || (name.starts_with('.') && !leaf.starts_with('.')) {
+                return None;
+            }
+
+            let is_dir = fs::metadata(entry.path()).is_ok_and(|meta| meta.is_dir());
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+
+    matches.sort();
+    matches
+}
+
+/// A regular file (following symlinks) with any execute bit set.
+pub fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
@@ -0,0 +1,126 @@
+use crate::cache;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `history` holds one `<count>\t<last used, unix seconds>\t<name>` line per launched entry.
+const HISTORY_FILE: &str = "history";
+/// The same for command lines launched with arguments, suggested again under their program.
+const COMMANDS_FILE: &str = "commands";
+
+/// How often an entry was launched, and when last.
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub count: u32,
+    pub last_used: u64,
+}
+
+/// `$XDG_STATE_HOME/deemenu`, or `~/.local/state/deemenu`
+fn state_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local").join("state"),
+    };
+    Some(base.join("deemenu"))
+}
+
+/// Counts one more launch of `name`. Failing to write only loses the statistic.
+pub fn record(name: &str) {
+    let Some(dir) = state_dir() else { return };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |age| age.as_secs());
+    if let Err(e) = record_in(&dir, HISTORY_FILE, name, now) {
+        eprintln!("deemenu: warning: can't write history in {}: {}", dir.display(), e);
+    }
+}
+
+/// Counts one more launch of the command line `command`, like `record`.
+pub fn record_command(command: &str) {
+    let Some(dir) = state_dir() else { return };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |age| age.as_secs());
+    if let Err(e) = record_in(&dir, COMMANDS_FILE, command, now) {
+        eprintln!("deemenu: warning: can't write history in {}: {}", dir.display(), e);
+    }
+}
+
+/// Every recorded command line, most launched first.
+pub fn commands() -> Vec<String> {
+    let records = state_dir().map(|dir| load_from(&dir, COMMANDS_FILE)).unwrap_or_default();
+    records.into_iter().map(|record| record.name).collect()
+}
+
+/// `--stats`: prints `<name>\t<count>\t<last used>` lines, most launched first.
+pub fn print_stats() {
+    let records = state_dir().map(|dir| load_from(&dir, HISTORY_FILE)).unwrap_or_default();
+    for record in records {
+        println!("{}\t{}\t{}", record.name, record.count, record.last_used);
+    }
+}
+
+/// `--recent`: every launched name, most recently used first.
+pub fn recent() -> Vec<String> {
+    let mut records = state_dir().map(|dir| load_from(&dir, HISTORY_FILE)).unwrap_or_default();
+    records.sort_by_key(|record| std::cmp::Reverse(record.last_used));
+    records.into_iter().map(|record| record.name).collect()
+}
+
+/// Every record, most launched first (then most recent, then by name). Unreadable lines are skipped.
+fn load_from(dir: &Path, file: &str) -> Vec<Record> {
+    let contents = fs::read_to_string(dir.join(file)).unwrap_or_default();
+    let mut records: Vec<Record> = contents.lines().filter_map(|line| {
+        let mut fields = line.splitn(3, '\t');
+        Some(Record {
+            count: fields.next()?.parse().ok()?,
+            last_used: fields.next()?.parse().ok()?,
+            name: fields.next().filter(|name| !name.is_empty())?.to_string(),
+        })
+    }).collect();
+
+    records.sort_by(|a, b| b.count.cmp(&a.count).then(b.last_used.cmp(&a.last_used)).then_with(|| a.name.cmp(&b.name)));
+    records
+}
+
+fn record_in(dir: &Path, file: &str, name: &str, now: u64) -> std::io::Result<()> {
+    let mut records = load_from(dir, file);
+    match records.iter_mut().find(|record| record.name == name) {
+        Some(record) => {
+            record.count += 1;
+            record.last_used = now;
+        }
+        None => records.push(Record { name: name.to_string(), count: 1, last_used: now }),
+    }
+
+    let contents: String = records.iter()
+        .map(|record| format!("{}\t{}\t{}\n", record.count, record.last_used, record.name))
+        .collect();
+
+    fs::create_dir_all(dir)?;
+    cache::write_atomically(&dir.join(file), &contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_launches_and_sorts_by_use() {
+        let dir = env::temp_dir().join(format!("deemenu-history-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(load_from(&dir, HISTORY_FILE), []);
+
+        record_in(&dir, HISTORY_FILE, "htop", 100).unwrap();
+        record_in(&dir, HISTORY_FILE, "Firefox Web Browser", 200).unwrap();
+        record_in(&dir, HISTORY_FILE, "htop", 300).unwrap();
+        record_in(&dir, COMMANDS_FILE, "htop -d 5", 300).unwrap();
+        let mut contents = fs::read_to_string(dir.join(HISTORY_FILE)).unwrap();
+        contents.push_str("garbage\n");
+        fs::write(dir.join(HISTORY_FILE), contents).unwrap();
+
+        let record = |name: &str, count, last_used| Record { name: name.to_string(), count, last_used };
+        assert_eq!(load_from(&dir, HISTORY_FILE), [record("htop", 2, 300), record("Firefox Web Browser", 1, 200)]);
+        assert_eq!(load_from(&dir, COMMANDS_FILE), [record("htop -d 5", 1, 300)]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
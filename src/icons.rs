@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Sizes tried in `hicolor`, closest to what the result list draws first.
+const SIZES: [&str; 8] = ["48x48", "64x64", "32x32", "128x128", "256x256", "24x24", "16x16", "scalable"];
+const EXTENSIONS: [&str; 2] = ["png", "svg"];
+
+/// Resolves `Icon=` values from desktop entries to image files,
+/// remembering each answer since many entries share an icon.
+pub struct IconResolver {
+    bases: Vec<PathBuf>,
+    resolved: HashMap<String, Option<PathBuf>>,
+}
+
+impl IconResolver {
+    pub fn new() -> Self {
+        let mut bases = Vec::new();
+        if let Some(home) = env::var_os("HOME").map(PathBuf::from) {
+            bases.push(home.join(".local/share/icons"));
+            bases.push(home.join(".icons"));
+        }
+        bases.push(PathBuf::from("/usr/local/share/icons"));
+        bases.push(PathBuf::from("/usr/share/icons"));
+
+        // Bases that don't exist would only cost stat calls for every icon
+        bases.retain(|base| base.is_dir());
+        Self { bases, resolved: HashMap::new() }
+    }
+
+    /// A PNG or SVG for the icon, or `None` if there's nothing we can draw.
+    pub fn resolve(&mut self, icon: &str) -> Option<PathBuf> {
+        if let Some(cached) = self.resolved.get(icon) {
+            return cached.clone();
+        }
+        let path = self.lookup(icon);
+        self.resolved.insert(icon.to_string(), path.clone());
+        path
+    }
+
+    fn lookup(&self, icon: &str) -> Option<PathBuf> {
+        let is_drawable = |path: &Path| {
+            path.is_file() && path.extension().is_some_and(|ext| EXTENSIONS.iter().any(|e| ext == *e))
+        };
+
+        if icon.starts_with('/') {
+            let path = PathBuf::from(icon);
+            return is_drawable(&path).then_some(path);
+        }
+
+        let theme_dirs = self.bases.iter().flat_map(|base| {
+            SIZES.iter().map(move |size| base.join("hicolor").join(size).join("apps"))
+        });
+
+        theme_dirs
+            .chain([PathBuf::from("/usr/share/pixmaps")])
+            .flat_map(|dir| EXTENSIONS.map(|ext| dir.join(format!("{}.{}", icon, ext))))
+            .find(|path| is_drawable(path))
+    }
+}
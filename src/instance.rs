@@ -0,0 +1,71 @@
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::thread;
+
+/// Outcome of trying to become the only running DeeMenu.
+pub enum Claim {
+    /// No other instance: this one listens for raise requests
+    Owner(UnixListener),
+    /// Another instance was running and has been asked to come to the front
+    Raised,
+}
+
+/// `$XDG_RUNTIME_DIR/deemenu.sock`
+fn socket_path() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|v| !v.is_empty())
+        .map(|dir| PathBuf::from(dir).join("deemenu.sock"))
+}
+
+/// Becomes the single instance, or raises the one already running.
+/// `None` if the socket can't be used; DeeMenu then just runs on its own.
+pub fn claim() -> Option<Claim> {
+    let Some(path) = socket_path() else {
+        eprintln!("deemenu: warning: XDG_RUNTIME_DIR is not set, single_instance is disabled");
+        return None;
+    };
+
+    // Second pass only happens after clearing a stale socket
+    for _ in 0..2 {
+        match UnixListener::bind(&path) {
+            Ok(listener) => return Some(Claim::Owner(listener)),
+            Err(e) if e.kind() == ErrorKind::AddrInUse => {
+                if let Ok(mut stream) = UnixStream::connect(&path) {
+                    if stream.write_all(b"raise\n").is_ok() {
+                        return Some(Claim::Raised);
+                    }
+                }
+                // Left behind by an instance that didn't exit cleanly
+                let _ = fs::remove_file(&path);
+            }
+            Err(e) => {
+                eprintln!("deemenu: warning: can't listen on {}: {}", path.display(), e);
+                return None;
+            }
+        }
+    }
+
+    None
+}
+
+/// Calls `on_raise` on a background thread whenever another launch asks this instance to come forward.
+pub fn serve(listener: UnixListener, on_raise: impl Fn() + Send + 'static) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut line = String::new();
+            if BufReader::new(stream).read_line(&mut line).is_ok() && line.trim() == "raise" {
+                on_raise();
+            }
+        }
+    });
+}
+
+/// Removes the socket when the owning instance exits, so the next launch doesn't probe a dead one.
+pub fn release() {
+    if let Some(path) = socket_path() {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,202 @@
+use eframe::egui;
+use serde::Deserialize;
+
+use crate::config::Layout;
+
+/// A key plus the exact modifiers that must be held, e.g. `Ctrl+N`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Binding {
+    pub key: egui::Key,
+    pub modifiers: egui::Modifiers,
+}
+
+impl Binding {
+    const fn plain(key: egui::Key) -> Self {
+        Self { key, modifiers: egui::Modifiers::NONE }
+    }
+
+    /// Parses `"Tab"`, `"j"`, `"Ctrl+N"`, `"Ctrl+Shift+Tab"`, ... (case-insensitive modifiers).
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key_name = parts.pop().unwrap_or_default();
+        let key = egui::Key::from_name(key_name)
+            .ok_or_else(|| format!("unknown key \"{}\" in binding \"{}\"", key_name, text))?;
+
+        let mut modifiers = egui::Modifiers::NONE;
+        for part in parts {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "shift" => modifiers.shift = true,
+                "alt" => modifiers.alt = true,
+                "super" | "cmd" | "command" => modifiers.mac_cmd = true,
+                other => return Err(format!("unknown modifier \"{}\" in binding \"{}\"", other, text)),
+            }
+        }
+
+        Ok(Self { key, modifiers })
+    }
+
+    fn matches(&self, input: &egui::InputState) -> bool {
+        input.key_pressed(self.key) && input.modifiers.matches_exact(self.modifiers)
+    }
+
+    /// Letters and the like also arrive as typed text, which must not reach the search box.
+    fn types_text(&self) -> bool {
+        !self.modifiers.ctrl && !self.modifiers.alt && !self.modifiers.mac_cmd
+            && self.key.symbol_or_name().chars().count() == 1
+    }
+}
+
+/// One or more bindings for the same action: `run = "Enter"` or `next = ["Tab", "Ctrl+N"]`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "BindingList")]
+pub struct Bindings(pub Vec<Binding>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BindingList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl TryFrom<BindingList> for Bindings {
+    type Error = String;
+
+    fn try_from(list: BindingList) -> Result<Self, Self::Error> {
+        let names = match list {
+            BindingList::One(name) => vec![name],
+            BindingList::Many(names) => names,
+        };
+        names.iter().map(|name| Binding::parse(name)).collect::<Result<_, _>>().map(Bindings)
+    }
+}
+
+impl Bindings {
+    fn of(keys: &[egui::Key]) -> Self {
+        Self(keys.iter().copied().map(Binding::plain).collect())
+    }
+
+    /// Whether any binding was pressed this frame. Swallows the text a plain
+    /// letter binding would otherwise type into the search box.
+    pub fn pressed(&self, input: &mut egui::InputState) -> bool {
+        let Some(binding) = self.0.iter().find(|binding| binding.matches(input)) else {
+            return false;
+        };
+
+        if binding.types_text() {
+            input.events.retain(|event| !matches!(event, egui::Event::Text(_)));
+        }
+        true
+    }
+
+    /// Like `pressed`, but the key never reaches the search box, for keys like
+    /// Home and End that would otherwise also move its cursor.
+    pub fn taken(&self, input: &mut egui::InputState) -> bool {
+        let Some(binding) = self.0.iter().find(|binding| binding.matches(input)).copied() else {
+            return false;
+        };
+
+        input.consume_key(binding.modifiers, binding.key);
+        if binding.types_text() {
+            input.events.retain(|event| !matches!(event, egui::Event::Text(_)));
+        }
+        true
+    }
+}
+
+/// The `[keys]` config section: which keys trigger each action.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Keys {
+    /// Unset: Right in the horizontal layout, Down in the vertical one
+    pub next: Option<Bindings>,
+    /// Unset: Left in the horizontal layout, Up in the vertical one
+    pub prev: Option<Bindings>,
+    pub run: Bindings,
+    /// Runs the command but stays open, showing its output
+    pub run_attached: Bindings,
+    pub close: Bindings,
+    /// Completes the highlighted name, or moves to the next one once completed
+    pub complete: Bindings,
+    pub first: Bindings,
+    pub last: Bindings,
+    /// Move the highlight by `page_size` results
+    pub page_up: Bindings,
+    pub page_down: Bindings,
+    /// Runs the highlighted entry with sudo, or not, until the query changes
+    pub sudo: Bindings,
+    /// Marks the highlighted entry to launch together with the other marked ones
+    pub mark: Bindings,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self {
+            next: None,
+            prev: None,
+            run: Bindings::of(&[egui::Key::Enter]),
+            run_attached: Bindings(vec![Binding { key: egui::Key::Enter, modifiers: egui::Modifiers::SHIFT }]),
+            close: Bindings::of(&[egui::Key::Escape]),
+            complete: Bindings::of(&[egui::Key::Tab]),
+            first: Bindings::of(&[egui::Key::Home]),
+            last: Bindings::of(&[egui::Key::End]),
+            page_up: Bindings::of(&[egui::Key::PageUp]),
+            page_down: Bindings::of(&[egui::Key::PageDown]),
+            sudo: Bindings(vec![Binding { key: egui::Key::S, modifiers: egui::Modifiers::CTRL }]),
+            mark: Bindings(vec![Binding { key: egui::Key::Space, modifiers: egui::Modifiers::CTRL }]),
+        }
+    }
+}
+
+impl Keys {
+    /// `(next, prev)` bindings, falling back to the arrows that follow the layout.
+    pub fn navigation(&self, layout: Layout) -> (Bindings, Bindings) {
+        let (next, prev) = match layout {
+            Layout::Horizontal => (egui::Key::ArrowRight, egui::Key::ArrowLeft),
+            Layout::Vertical => (egui::Key::ArrowDown, egui::Key::ArrowUp),
+        };
+        (
+            self.next.clone().unwrap_or_else(|| Bindings::of(&[next])),
+            self.prev.clone().unwrap_or_else(|| Bindings::of(&[prev])),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys_with_modifiers() {
+        assert_eq!(Binding::parse("Tab"), Ok(Binding::plain(egui::Key::Tab)));
+        assert_eq!(Binding::parse("j"), Ok(Binding::plain(egui::Key::J)));
+        assert_eq!(
+            Binding::parse("ctrl+Shift+N"),
+            Ok(Binding { key: egui::Key::N, modifiers: egui::Modifiers::CTRL | egui::Modifiers::SHIFT })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert!(Binding::parse("Ctrl+Nope").is_err());
+        assert!(Binding::parse("Hyper+N").is_err());
+        assert!(Binding::parse("").is_err());
+    }
+
+    #[test]
+    fn accepts_one_binding_or_a_list() {
+        let keys: Keys = toml::from_str("run = \"Ctrl+J\"\nnext = [\"Tab\", \"Ctrl+N\"]").unwrap();
+        assert_eq!(keys.run.0.len(), 1);
+        assert_eq!(keys.next.unwrap().0.len(), 2);
+        assert_eq!(keys.close, Bindings::of(&[egui::Key::Escape]));
+
+        assert!(toml::from_str::<Keys>("close = \"Escpae\"").is_err());
+    }
+
+    #[test]
+    fn navigation_follows_layout_by_default() {
+        let (next, prev) = Keys::default().navigation(Layout::Vertical);
+        assert_eq!(next, Bindings::of(&[egui::Key::ArrowDown]));
+        assert_eq!(prev, Bindings::of(&[egui::Key::ArrowUp]));
+    }
+}
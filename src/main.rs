@@ -1,411 +1,2026 @@
+mod cache;
+mod calc;
+mod cli;
+mod completions;
+mod config;
+mod core;
+mod desktop;
+mod emoji;
+mod exports;
+mod files;
+mod history;
+mod icons;
+mod instance;
+mod keys;
+mod monitor;
+mod output;
+mod prefix;
+mod procs;
+mod shell;
+mod web;
+
+use crate::core::{AppMode, Candidate, Core};
+use config::{Config, Layout, Notify, WindowType};
 use eframe::egui;
+use winit::platform::x11::EventLoopBuilderExtX11;
+use prefix::Action;
 use std::collections::HashSet;
 use std::env;
+use std::ffi::OsStr;
 use std::fs;
 use std::io::Write;
+use std::os::unix::net::UnixListener;
+use std::os::unix::process::CommandExt;
 use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
+use zeroize::{Zeroize, Zeroizing};
 
 fn main() -> eframe::Result<()> {
+    // Silent unless asked for, e.g. `RUST_LOG=debug deemenu`
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("off")).init();
+    let args = cli::parse();
+    if args.stats {
+        history::print_stats();
+        return Ok(());
+    }
+    // Never opens a window, so it works from a key binding without a display too
+    if args.last {
+        let mut config = Config::load(args.config.as_deref());
+        apply_args(&mut config, &args);
+        std::process::exit(run_last(config, &args));
+    }
+
+    // eframe would only fail with a backend error, e.g. from a hotkey firing on a text console
+    if !monitor::has_display() {
+        eprintln!("deemenu: no display server detected (neither $DISPLAY nor $WAYLAND_DISPLAY is set)");
+        std::process::exit(3);
+    }
+
+    let mut config = Config::load(args.config.as_deref());
+    apply_args(&mut config, &args);
+
+    // Choices must be read before the window opens; a script is waiting on us
+    let choices = args.dmenu.then(|| read_choices(&args));
+
+    // A dmenu script wants its own menu, not to raise someone else's
+    let listener = if config.single_instance && !args.dmenu {
+        match instance::claim() {
+            Some(instance::Claim::Raised) => return Ok(()),
+            Some(instance::Claim::Owner(listener)) => Some(listener),
+            None => None,
+        }
+    } else {
+        None
+    };
+    let owns_socket = listener.is_some();
+
+    let monitor = monitor::resolve(config.monitor).map(|m| m.rect);
+    if config.position.is_some() && !monitor::can_position(config.xwayland) {
+        eprintln!("deemenu: warning: Wayland compositors ignore window positions; set xwayland = true to use `position`");
+    }
+    if config.window_type != WindowType::Normal && !monitor::can_position(config.xwayland) {
+        eprintln!("deemenu: warning: window_type only applies on X11; set xwayland = true to use it on Wayland");
+    }
+
     // Setup options: Undecorated, Top of screen, Fixed height
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_decorations(false)
             .with_always_on_top()
-            .with_inner_size([1920.0, 40.0])
-            .with_position(egui::pos2(0.0, 0.0)),
+            .with_transparent(config.is_transparent())
+            .with_window_type(config.x11_window_type())
+            .with_inner_size(config.window_size(monitor.map(|m| m.width())))
+            .with_position(config.window_position(monitor)),
+        // XWayland windows may position themselves, native Wayland ones can't
+        event_loop_builder: (config.xwayland && monitor::is_wayland()).then(|| -> eframe::EventLoopBuilderHook {
+            Box::new(|builder| {
+                builder.with_x11();
+            })
+        }),
         ..Default::default()
     };
 
-    eframe::run_native(
+    let launched = Arc::new(AtomicBool::new(false));
+    let app_launched = Arc::clone(&launched);
+
+    let result = eframe::run_native(
         "DeeMenu",
         options,
-        Box::new(|cc| Ok(Box::new(DeeMenu::new(cc)))),
-    )
+        Box::new(move |cc| {
+            let mut app = DeeMenu::new(cc, config, monitor, choices);
+            app.dry_run = args.dry_run;
+            app.print_command = args.print_command;
+            if args.recent && !args.dmenu {
+                app.core.recent = history::recent();
+                app.core.update_filter();
+            }
+            if app.config.history && !args.dmenu {
+                app.core.invocations = history::commands();
+                app.core.update_filter();
+            }
+            app.launched = app_launched;
+            if let Some(listener) = listener {
+                app.listen_for_raises(listener);
+            }
+            app.args = args;
+            Ok(Box::new(app))
+        }),
+    );
+
+    if owns_socket {
+        instance::release();
+    }
+    result?;
+
+    // Scripts can tell a launch from a cancelled menu
+    if !launched.load(Ordering::Relaxed) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `--last`: launches the most recently used entry again without opening a
+/// window. Returns the exit code: 1 when there is nothing to run or it failed.
+fn run_last(config: Config, args: &cli::Args) -> i32 {
+    let Some(name) = history::recent().into_iter().next() else {
+        eprintln!("deemenu: nothing launched yet");
+        return 1;
+    };
+
+    let mut app = DeeMenu::from_config(config, None, egui::Context::default());
+    app.dry_run = args.dry_run;
+    app.print_command = args.print_command;
+    let ctx = app.ctx.clone();
+    app.start_scan(&ctx);
+    app.finish_scan();
+
+    let Some(candidate) = app.core.all_executables.iter().find(|c| c.name == name).cloned() else {
+        eprintln!("deemenu: '{}' isn't installed anymore", name);
+        return 1;
+    };
+    let command = prefix::expand_alias(candidate.command(), &app.core.aliases);
+    app.start(&command, candidate.terminal);
+
+    match app.launch_rx.recv() {
+        Ok(LaunchEvent::Launched) => {
+            if app.config.history && !app.dry_run {
+                history::record(&name);
+            }
+            0
+        }
+        Ok(LaunchEvent::Failed(reason)) => {
+            eprintln!("deemenu: failed to launch: {}", reason);
+            1
+        }
+        _ => 1,
+    }
+}
+
+/// Command line options take precedence over the config file.
+fn apply_args(config: &mut Config, args: &cli::Args) {
+    if args.strict_exec {
+        config.strict_exec = true;
+    }
+    if args.no_cache {
+        config.cache = false;
+    }
+    if let Some(max_results) = args.max_results {
+        config.max_results = max_results;
+    }
+    if let Some(prompt) = args.prompt.clone() {
+        config.prompt = prompt;
+    }
+}
+
+/// Colors, fonts and text size from the config.
+fn apply_style(ctx: &egui::Context, config: &Config) {
+    let mut visuals = egui::Visuals::dark();
+    visuals.override_text_color = Some(config.text());
+    visuals.panel_fill = config.panel();
+    ctx.set_visuals(visuals);
+
+    // The search box width is measured with these too, so wide glyphs get their room
+    let mut fonts = custom_fonts(config).unwrap_or_default();
+    add_fallback_font(&mut fonts, &CJK_FONTS);
+    ctx.set_fonts(fonts);
+
+    let mut style = (*ctx.style()).clone();
+    style.text_styles.insert(
+        egui::TextStyle::Body,
+        egui::FontId::new(config.font_size, egui::FontFamily::Monospace),
+    );
+    // A plain mouse wheel scrolls the horizontal result strip too
+    style.always_scroll_the_only_direction = true;
+    ctx.set_style(style);
+}
+
+/// Where to cut a name longer than `max_chars` characters so that, with an
+/// ellipsis added, it is `max_chars` long. `None` if it fits (or there's no limit).
+fn ellipsis_cut(name: &str, max_chars: usize) -> Option<usize> {
+    if max_chars == 0 || name.chars().nth(max_chars).is_none() {
+        return None;
+    }
+    name.char_indices().nth(max_chars - 1).map(|(i, _)| i)
+}
+
+/// The default fonts with the configured `font` put first in every family,
+/// so glyphs it lacks still come from the built-in ones. `None` (after a
+/// warning) if there's no font configured or it can't be read.
+fn custom_fonts(config: &Config) -> Option<egui::FontDefinitions> {
+    let path = files::expand_tilde(config.font.as_deref()?);
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("deemenu: warning: can't load font {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    // Checked here, since egui would only fail once it lays out text
+    if let Err(e) = ab_glyph::FontRef::try_from_slice(&bytes) {
+        eprintln!("deemenu: warning: can't use font {}: {}", path.display(), e);
+        return None;
+    }
+
+    let name = config.font_name.clone()
+        .unwrap_or_else(|| path.file_stem().map_or("custom".to_string(), |stem| stem.to_string_lossy().to_string()));
+
+    let mut fonts = egui::FontDefinitions::default();
+    fonts.font_data.insert(name.clone(), egui::FontData::from_owned(bytes));
+    for family in [egui::FontFamily::Monospace, egui::FontFamily::Proportional] {
+        fonts.families.entry(family).or_default().insert(0, name.clone());
+    }
+    Some(fonts)
+}
+
+/// Where distributions install fonts covering Chinese, Japanese and Korean,
+/// which neither the built-in fonts nor most Latin ones have glyphs for.
+const CJK_FONTS: [&str; 7] = [
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/usr/share/fonts/wenquanyi/wqy-microhei/wqy-microhei.ttc",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/google-droid/DroidSansFallbackFull.ttf",
+];
+
+/// Appends the first usable font in `paths` to every family, so localized
+/// names (`ファイル`, `文件管理器`) don't render as boxes. Returns whether one was found.
+fn add_fallback_font(fonts: &mut egui::FontDefinitions, paths: &[&str]) -> bool {
+    let Some((path, bytes)) = paths.iter()
+        .filter_map(|path| fs::read(path).ok().map(|bytes| (*path, bytes)))
+        .find(|(_, bytes)| ab_glyph::FontRef::try_from_slice(bytes).is_ok())
+    else {
+        return false;
+    };
+    log::debug!("using {} as fallback font", path);
+
+    fonts.font_data.insert("fallback".to_string(), egui::FontData::from_owned(bytes));
+    for family in [egui::FontFamily::Monospace, egui::FontFamily::Proportional] {
+        fonts.families.entry(family).or_default().push("fallback".to_string());
+    }
+    true
+}
+
+/// `--dmenu` choices: the non-empty lines of stdin, split into fields as asked.
+fn read_choices(args: &cli::Args) -> Vec<Candidate> {
+    let fields = core::Fields { delimiter: args.delimiter.clone(), with_nth: args.with_nth, accept_nth: args.accept_nth };
+    std::io::stdin()
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Candidate::choice(&line, &fields))
+        .collect()
+}
+
+/// Alt plus one of these runs the first to ninth visible result
+const QUICK_PICK_KEYS: [egui::Key; 9] = [
+    egui::Key::Num1, egui::Key::Num2, egui::Key::Num3, egui::Key::Num4, egui::Key::Num5,
+    egui::Key::Num6, egui::Key::Num7, egui::Key::Num8, egui::Key::Num9,
+];
+
+/// Laid out result names by name, highlight state, matched range, mark and pin,
+/// kept between frames in egui's temp data so unchanged results are never laid out again
+type NameGalleys = std::collections::HashMap<(String, bool, Option<std::ops::Range<usize>>, bool, bool), Arc<egui::Galley>>;
+
+/// Past this many cached names the cache starts over, as queries come and go
+const MAX_NAME_GALLEYS: usize = 1024;
+
+/// Results streamed back from the background scan thread
+enum ScanEvent {
+    Candidates(Vec<Candidate>),
+    TerminalApps(HashSet<String>),
+}
+
+/// What a click on a result (or an entry of its right-click menu) asks for
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ResultClick {
+    Run,
+    Sudo,
+    /// Middle click
+    Terminal,
+    Copy,
+    /// Ctrl+click: mark it to launch with the other marked ones
+    Mark,
 }
 
-#[derive(PartialEq)]
-enum AppMode {
-    Search,
-    SudoPassword,
+/// Outcome of a launch, reported back from the spawn thread
+enum LaunchEvent {
+    Launched,
+    Failed(String),
+    WrongPassword,
 }
 
 struct DeeMenu {
     // --- Logic State ---
-    all_executables: Vec<String>,
-    filtered_executables: Vec<String>,
-    search_query: String,
-    password_query: String,
-    selected_index: usize,
-    mode: AppMode,
-    pending_sudo_command: String,
+    /// Candidates, query, highlight and mode; see `core::Core`
+    core: Core,
+    password_query: Zeroizing<String>,
+    terminal_apps: HashSet<String>,
+    scan_rx: Option<Receiver<ScanEvent>>,
+    launch_tx: Sender<LaunchEvent>,
+    launch_rx: Receiver<LaunchEvent>,
+    /// Launches started but not reported back yet; a batch closes the window once all have
+    launching: usize,
+    /// Whether any launch of the current batch has started
+    batch_started: bool,
+    /// Names and typed command line the launch in flight counts in the history once it has started
+    pending_history: Option<(Vec<String>, Option<String>)>,
+    /// Shift+Enter: a command whose output is shown in the window
+    output: Option<output::CommandOutput>,
+    /// `--dry-run`: print launches instead of running them
+    dry_run: bool,
+    /// `--print-command`: print the chosen command line to stdout for a wrapper to run
+    print_command: bool,
+    /// Command line options, applied again when `deemenu:reload` re-reads the config
+    args: cli::Args,
+    /// `--dmenu`: Enter prints the choice to stdout instead of running it
+    dmenu: bool,
+    /// Set once something ran (or a result was copied); read by `main` for the exit status
+    launched: Arc<AtomicBool>,
+    /// Raise requests from later launches in single-instance mode
+    raise_rx: Option<Receiver<()>>,
 
     // --- UI State ---
-    startup_counter: u8,
+    /// Whether the window sits where the config wants it (see `update`)
+    positioned: bool,
+    /// Ask the window manager for focus on the next frame
+    raise_window: bool,
+    /// Give the visible input box keyboard focus on the next frame
+    focus_input: bool,
+    /// Whether the window has had keyboard focus yet; until then losing it means nothing
+    was_focused: bool,
+    /// Inner size last asked for; the height follows the results, the width the monitor
+    window_size: egui::Vec2,
+    /// Monitor width in points, which unlike xrandr's pixels accounts for HiDPI scaling once egui reports it
+    monitor_width: Option<f32>,
+    /// Wheel movement not yet turned into a selection step (`scroll_selects`)
+    scroll_accum: f32,
+    error_message: Option<String>,
+    password_error: bool,
+    config: Config,
+    ctx: egui::Context,
 }
 
 impl DeeMenu {
-    fn new(cc: &eframe::CreationContext) -> Self {
-        // Visual Style
-        let mut visuals = egui::Visuals::dark();
-        visuals.override_text_color = Some(egui::Color32::WHITE);
-        visuals.panel_fill = egui::Color32::from_rgb(35, 36, 41);
-        cc.egui_ctx.set_visuals(visuals);
-
-        let mut style = (*cc.egui_ctx.style()).clone();
-        style.text_styles.insert(
-            egui::TextStyle::Body,
-            egui::FontId::new(14.0, egui::FontFamily::Monospace),
-        );
-        cc.egui_ctx.set_style(style);
-
-        let mut app = Self {
-            all_executables: Vec::new(),
-            filtered_executables: Vec::new(),
-            search_query: String::new(),
-            password_query: String::new(),
-            selected_index: 0,
-            mode: AppMode::Search,
-            pending_sudo_command: String::new(),
-            startup_counter: 0,
-        };
+    /// `choices` are the `--dmenu` lines read from stdin; without them PATH is scanned.
+    fn new(cc: &eframe::CreationContext, config: Config, monitor: Option<egui::Rect>, choices: Option<Vec<Candidate>>) -> Self {
+        apply_style(&cc.egui_ctx, &config);
+
+        // PNG/SVG loading for result icons; textures are cached by file URI
+        egui_extras::install_image_loaders(&cc.egui_ctx);
 
-        app.scan_path();
+        let mut app = Self::from_config(config, monitor, cc.egui_ctx.clone());
+        match choices {
+            Some(choices) => app.use_choices(choices),
+            None => app.start_scan(&cc.egui_ctx),
+        }
         app
     }
 
-    /// Scans PATH + Standard Linux Directories (Permissive Mode)
-    fn scan_path(&mut self) {
-        let mut binaries = HashSet::new();
-
-        // 1. Get paths from Environment
-        let path_var = env::var("PATH").unwrap_or_default();
-        let mut paths_to_scan: Vec<String> = env::split_paths(&path_var)
-            .map(|p| p.to_string_lossy().to_string())
-            .collect();
-
-        // 2. Force add standard directories (to catch /usr/bin if PATH is minimal)
-        let fallback_paths = [
-            "/usr/bin",
-            "/usr/local/bin",
-            "/bin",
-            "/snap/bin",
-            "/var/lib/flatpak/exports/bin",
-            "/sbin",
-            "/usr/sbin"
-        ];
+    /// `--dmenu`: pick from the given lines, in their order, without any prefixes.
+    fn use_choices(&mut self, choices: Vec<Candidate>) {
+        self.dmenu = true;
+        self.core.use_choices(choices);
+    }
 
-        for fallback in fallback_paths {
-            let p = fallback.to_string();
-            if !paths_to_scan.contains(&p) {
-                paths_to_scan.push(p);
-            }
+    /// Builds the app state without touching the window or starting the scan.
+    fn from_config(config: Config, monitor: Option<egui::Rect>, ctx: egui::Context) -> Self {
+        let (launch_tx, launch_rx) = mpsc::channel();
+
+        Self {
+            core: Core::new(&config),
+            password_query: Zeroizing::new(String::with_capacity(128)),
+            terminal_apps: HashSet::new(),
+            scan_rx: None,
+            launch_tx,
+            launch_rx,
+            launching: 0,
+            batch_started: false,
+            pending_history: None,
+            output: None,
+            dry_run: false,
+            print_command: false,
+            args: cli::Args::default(),
+            dmenu: false,
+            launched: Arc::new(AtomicBool::new(false)),
+            raise_rx: None,
+            // The ViewportBuilder already placed the window when the monitor is known,
+            // and on native Wayland the compositor decides anyway
+            positioned: monitor.is_some() || !monitor::can_position(config.xwayland),
+            raise_window: true,
+            focus_input: true,
+            was_focused: false,
+            window_size: egui::Vec2::from(config.window_size(monitor.map(|m| m.width()))),
+            monitor_width: monitor.map(|m| m.width()),
+            scroll_accum: 0.0,
+            error_message: None,
+            password_error: false,
+            config,
+            ctx,
         }
+    }
 
-        for path_str in &paths_to_scan {
-            let path = Path::new(path_str);
+    /// Kicks off the PATH/desktop-entry scan on a worker thread so the window
+    /// can appear immediately. Results arrive through `scan_rx` in batches.
+    fn start_scan(&mut self, ctx: &egui::Context) {
+        let (tx, rx) = mpsc::channel();
+        self.scan_rx = Some(rx);
+
+        let ctx = ctx.clone();
+        let dirs = core::scan_dirs(&self.config);
+        let strict_exec = self.config.strict_exec;
+        let full_paths = self.config.full_paths;
+        // The cache only knows names, not where they were found
+        let use_cache = self.config.cache && !full_paths;
+        let cache_desktop = self.config.cache;
+        thread::spawn(move || {
+            core::scan_path(&dirs, strict_exec, full_paths, use_cache, |batch| {
+                let sent = tx.send(ScanEvent::Candidates(batch)).is_ok();
+                ctx.request_repaint();
+                sent
+            });
 
-            if !path.exists() { continue; }
+            let entries = desktop::scan(cache_desktop);
 
-            if let Ok(entries) = fs::read_dir(path) {
-                for entry in entries.flatten() {
-                    let name = entry.file_name().to_string_lossy().to_string();
+            // Binaries whose desktop entries declare Terminal=true
+            let terminal_apps = entries
+                .iter()
+                .filter(|entry| entry.terminal)
+                .filter_map(|entry| entry.binary())
+                .collect();
+            let _ = tx.send(ScanEvent::TerminalApps(terminal_apps));
 
-                    // Skip hidden files
-                    if name.starts_with('.') { continue; }
+            let mut icons = icons::IconResolver::new();
+            let apps = entries
+                .iter()
+                .filter(|entry| !entry.no_display)
+                .map(|entry| {
+                    let icon = entry.icon.as_deref().and_then(|icon| icons.resolve(icon));
+                    Candidate::from_desktop(entry, icon)
+                })
+                .collect();
+            let _ = tx.send(ScanEvent::Candidates(apps));
+            ctx.request_repaint();
+        });
+    }
 
-                    // PERMISSIVE CHECK:
-                    // If it is in a bin folder and not a directory, assume it is executable.
-                    // This fixes issues where symlinks (like firefox -> ../lib/firefox/firefox.sh)
-                    // were being ignored by strict metadata checks.
-                    if let Ok(file_type) = entry.file_type() {
-                        if !file_type.is_dir() {
-                             binaries.insert(name);
-                        }
-                    }
-                }
+    /// Waits for the whole scan, for `--last`, which has no frames to merge it in.
+    fn finish_scan(&mut self) {
+        while let Some(event) = self.scan_rx.as_ref().map(|rx| rx.recv()) {
+            match event {
+                Ok(ScanEvent::Candidates(batch)) => self.core.merge(batch),
+                Ok(ScanEvent::TerminalApps(apps)) => self.terminal_apps = apps,
+                Err(_) => self.scan_rx = None,
             }
         }
-
-        self.all_executables = binaries.into_iter().collect();
-        self.all_executables.sort();
-        self.update_filter();
     }
 
-    fn update_filter(&mut self) {
-        let query = self.search_query.trim().to_lowercase();
+    /// Merges any finished scan batches into the candidate list.
+    fn drain_scan(&mut self) {
+        let Some(rx) = &self.scan_rx else { return };
 
-        // Handle sudo prefix logic for filtering
-        let clean_query = if query.starts_with("sudo ") {
-            query.strip_prefix("sudo ").unwrap_or("").to_string()
-        } else {
-            query.clone()
-        };
+        let mut new_candidates = Vec::new();
+        let mut finished = false;
+        loop {
+            match rx.try_recv() {
+                Ok(ScanEvent::Candidates(batch)) => new_candidates.extend(batch),
+                Ok(ScanEvent::TerminalApps(apps)) => self.terminal_apps = apps,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.scan_rx = None;
+                    finished = true;
+                    break;
+                }
+            }
+        }
 
-        if clean_query.is_empty() {
-            self.filtered_executables = self.all_executables.iter().take(50).cloned().collect();
-        } else {
-            self.filtered_executables = self.all_executables
-                .iter()
-                .filter(|name| name.to_lowercase().contains(&clean_query))
-                .take(50)
-                .cloned()
-                .collect();
+        if !new_candidates.is_empty() {
+            self.core.merge(new_candidates);
         }
 
-        // Safety bounds
-        if self.filtered_executables.is_empty() {
-            self.selected_index = 0;
-        } else if self.selected_index >= self.filtered_executables.len() {
-            self.selected_index = self.filtered_executables.len() - 1;
+        // Nothing at all usually means a broken environment rather than an empty system.
+        // Said once, when the scan ends; `--dmenu` lists stdin, not PATH.
+        if finished && !self.dmenu && self.core.all_executables.is_empty() {
+            eprintln!("deemenu: warning: no programs found in PATH or the standard directories");
+            self.error_message = Some("no programs found, check PATH".to_string());
         }
     }
 
-    fn attempt_run(&mut self) -> bool {
-        match self.mode {
+    /// Returns true if the window should close right away. Launches report
+    /// back through `launch_rx` instead, so failures can keep it open.
+    /// `attached` launches stay open and show the command's output instead.
+    fn attempt_run(&mut self, attached: bool) -> bool {
+        if self.launching > 0 { return false; }
+
+        match self.core.mode {
+            AppMode::Search if self.dmenu => {
+                let Some(choice) = self.core.dmenu_choice(attached) else { return false };
+                println!("{}", choice);
+                self.launched.store(true, Ordering::Relaxed);
+                return true;
+            }
             AppMode::Search => {
-                let raw_cmd = self.search_query.trim();
-
-                // 1. Detect Sudo Request
-                if raw_cmd.starts_with("sudo ") {
-                    let actual_cmd = raw_cmd.strip_prefix("sudo ").unwrap().trim();
-                    if !actual_cmd.is_empty() {
-                        self.pending_sudo_command = actual_cmd.to_string();
-                        self.mode = AppMode::SudoPassword;
-                        self.selected_index = 0;
-                        return false; // Don't close, wait for password
+                // Shift+Enter on a file lists the apps to open it with instead
+                if attached {
+                    if let Some(path) = self.core.selected_file() {
+                        self.open_with(path);
+                        return false;
                     }
-                    return false;
                 }
 
-                // 2. Determine Command
-                // If user typed arguments (spaces) OR no match found, use raw input.
-                // Otherwise use the selected suggestion.
-                let cmd_to_run = if !self.filtered_executables.is_empty() {
-                    if raw_cmd.contains(' ') {
-                        raw_cmd.to_string()
-                    } else {
-                        self.filtered_executables[self.selected_index].clone()
+                // `=` stays open for the next calculation; Shift+Enter copies and closes
+                if !attached {
+                    if let Some(result) = self.core.keep_calculation() {
+                        self.error_message = (!copy_to_clipboard(&result)).then(|| CLIPBOARD_FAILED.to_string());
+                        move_cursor_to_end(&self.ctx, egui::Id::new("search_query"), &self.core.search_query);
+                        return false;
                     }
-                } else {
-                    raw_cmd.to_string()
-                };
+                }
 
-                if !cmd_to_run.is_empty() {
-                    self.spawn_process(&cmd_to_run, false, None);
-                    return true;
+                // Marked entries launch instead of the highlighted one
+                let names = match self.core.filtered_executables.get(self.core.selected_index) {
+                    _ if !self.core.marked.is_empty() => self.core.marked.clone(),
+                    selected => selected.map(|c| c.name.clone()).into_iter().collect(),
+                };
+                let invocation = self.core.invocation();
+                let action = self.core.resolve();
+                // Counted once it has started, see `record_history`
+                self.pending_history = matches!(action, Action::Launch { .. } | Action::Sudo(_) | Action::Batch(_))
+                    .then_some((names, invocation));
+                if self.core.needs_confirmation(&action) {
+                    self.core.ask_confirmation(action, attached);
+                    return false;
+                }
+                return self.perform(action, attached);
+            }
+            AppMode::Confirm => {
+                if let Some((action, attached)) = self.core.confirmed() {
+                    self.focus_input = true;
+                    return self.perform(action, attached);
                 }
             }
             AppMode::SudoPassword => {
                 if !self.password_query.is_empty() {
-                    self.spawn_process(&self.pending_sudo_command, true, Some(self.password_query.clone()));
+                    self.launching = 1;
+                    let password = std::mem::take(&mut self.password_query);
+                    self.spawn_process(&self.core.pending_sudo_command, true, false, Some(password));
+                }
+            }
+        }
+        false
+    }
+
+    /// Carries out a click on result `i`. Running it in a terminal or with sudo
+    /// goes through `term:` and the Ctrl+S mark, so confirmations still apply.
+    /// Returns true if the window should close right away.
+    fn click_result(&mut self, i: usize, click: ResultClick) -> bool {
+        self.core.selected_index = i;
+        let name = self.core.filtered_executables[i].name.clone();
+
+        match click {
+            ResultClick::Mark => {
+                self.core.toggle_mark();
+                return false;
+            }
+            ResultClick::Copy => return self.perform(Action::Copy(name), false),
+            ResultClick::Run if self.core.copies_selection() => {}
+            ResultClick::Run => self.core.search_query = name,
+            ResultClick::Terminal => self.core.search_query = format!("term: {}", name),
+            ResultClick::Sudo => {
+                self.core.search_query = name;
+                self.core.sudo_selection = true;
+            }
+        }
+        self.attempt_run(false)
+    }
+
+    /// `scroll_selects`: the wheel (or touchpad) steps the highlight, one entry
+    /// per wheel notch, instead of scrolling the list.
+    fn scroll_selection(&mut self, ctx: &egui::Context) {
+        let notch = ctx.options(|o| o.line_scroll_speed);
+        let delta = ctx.input_mut(|i| {
+            i.smooth_scroll_delta = egui::Vec2::ZERO;
+            i.raw_scroll_delta
+        });
+
+        // Up/left is positive and goes back; touchpads arrive in small pieces
+        self.scroll_accum += delta.x + delta.y;
+        while self.scroll_accum >= notch {
+            self.core.select_previous();
+            self.scroll_accum -= notch;
+        }
+        while self.scroll_accum <= -notch {
+            self.core.select_next();
+            self.scroll_accum += notch;
+        }
+    }
+
+    /// Carries out what a prefix (or the plain launcher) decided Enter should do.
+    /// Returns true if the window should close right away.
+    fn perform(&mut self, action: Action, attached: bool) -> bool {
+        match action {
+            Action::Nothing => {}
+            Action::Copy(text) => {
+                // Stay open so the hint is seen; the text went to stdout instead
+                if !copy_to_clipboard(&text) {
+                    self.error_message = Some(CLIPBOARD_FAILED.to_string());
+                    return false;
+                }
+                self.launched.store(true, Ordering::Relaxed);
+                return true;
+            }
+            Action::Signal(pid) => {
+                // Shift+Enter kills outright, Enter asks the process to quit
+                let signal = if attached { "KILL" } else { "TERM" };
+                if self.dry_run {
+                    eprintln!("deemenu: dry run: kill -{} {}", signal, pid);
+                    self.launched.store(true, Ordering::Relaxed);
                     return true;
                 }
+                match procs::signal(pid, signal) {
+                    Ok(()) => {
+                        self.launched.store(true, Ordering::Relaxed);
+                        return true;
+                    }
+                    Err(e) => self.error_message = Some(format!("can't signal {}: {}", pid, e)),
+                }
+            }
+            Action::SetQuery(query) => {
+                self.core.set_query(query);
+                move_cursor_to_end(&self.ctx, egui::Id::new("search_query"), &self.core.search_query);
+            }
+            Action::Sudo(command) if self.print_command => {
+                self.launching += 1;
+                self.spawn_process(&command, true, false, None);
+            }
+            Action::Sudo(command) => {
+                // Don't close, wait for password
+                self.core.ask_sudo_password(command);
+                self.focus_input = true;
+            }
+            Action::Launch { command, .. } if attached && !self.print_command => self.run_attached(&command),
+            Action::Launch { command, in_terminal } => self.launch(&command, in_terminal),
+            Action::Batch(actions) => {
+                for action in actions {
+                    if let Action::Launch { command, in_terminal } = action {
+                        self.start(&command, in_terminal);
+                    }
+                }
             }
+            Action::EditConfig => self.edit_config(),
+            Action::Reload => self.reload(),
+            Action::Error(message) => self.error_message = Some(message),
         }
         false
     }
 
-    fn spawn_process(&self, cmd_str: &str, is_sudo: bool, password: Option<String>) {
-        let cmd_str = cmd_str.to_string();
+    /// `deemenu:config`: opens the config file with `xdg-open`, creating an empty one first if needed.
+    fn edit_config(&mut self) {
+        let Some(path) = self.args.config.clone().or_else(Config::default_path) else {
+            self.error_message = Some("no config location, HOME is unset".to_string());
+            return;
+        };
+        if !path.exists() {
+            let created = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, ""));
+            if let Err(e) = created {
+                self.error_message = Some(format!("can't create {}: {}", path.display(), e));
+                return;
+            }
+        }
+        self.launch(&format!("xdg-open {}", shell::quote(&path.to_string_lossy())), false);
+    }
+
+    /// `deemenu:reload`: applies the config file again and rescans, keeping
+    /// command line options and what was launched this run.
+    fn reload(&mut self) {
+        let mut config = Config::load(self.args.config.as_deref());
+        apply_args(&mut config, &self.args);
+        apply_style(&self.ctx, &config);
+
+        // Colors and font size may have changed
+        self.ctx.data_mut(|d| d.remove::<NameGalleys>(egui::Id::new("name_galleys")));
+
+        let mut core = Core::new(&config);
+        core.launch_counts = std::mem::take(&mut self.core.launch_counts);
+        core.recent = std::mem::take(&mut self.core.recent);
+        self.core = core;
+        self.config = config;
+        self.error_message = None;
+        self.focus_input = true;
+
+        let ctx = self.ctx.clone();
+        self.start_scan(&ctx);
+    }
+
+    /// Lists the apps whose desktop entries open `path`'s file type.
+    fn open_with(&mut self, path: std::path::PathBuf) {
+        match files::mime_type(&path) {
+            Some(mime) => {
+                self.error_message = None;
+                self.core.start_open_with(path, mime);
+                move_cursor_to_end(&self.ctx, egui::Id::new("search_query"), &self.core.search_query);
+            }
+            None => self.error_message = Some(format!("can't tell the file type of {}", path.display())),
+        }
+    }
+
+    /// Starts a command line, unless it looks like a typo.
+    fn launch(&mut self, command: &str, in_terminal: bool) {
+        if command.is_empty() { return; }
+
+        // A lone word that matches nothing is most likely a typo; don't blindly exec it.
+        // Paths still run if they exist, and while the scan is running we can't tell yet.
+        if self.core.filtered_executables.is_empty() && !command.contains(' ') {
+            let exists = if command.contains('/') {
+                files::expand_tilde(command).exists()
+            } else {
+                self.scan_rx.is_some()
+            };
+            if !exists {
+                self.error_message = Some(format!("command not found: {}", command));
+                return;
+            }
+        }
+
+        self.start(command, in_terminal);
+    }
+
+    /// Starts a command line in the background, in a terminal if it wants one.
+    fn start(&mut self, command: &str, in_terminal: bool) {
+        // Compare by name, since `full_paths` launches `/usr/bin/htop` rather than `htop`
+        let needs_terminal = in_terminal
+            || shell::split(command)
+                .and_then(|parts| parts.into_iter().next())
+                .and_then(|bin| Path::new(&bin).file_name().map(|name| name.to_string_lossy().to_string()))
+                .is_some_and(|bin| self.terminal_apps.contains(&bin));
+
+        self.launching += 1;
+        self.spawn_process(command, false, needs_terminal, None);
+    }
+
+    /// Runs a command with its output streamed into the window, which stays open.
+    fn run_attached(&mut self, command: &str) {
+        let parts = match shell::command_parts(command) {
+            Some(parts) if !parts.is_empty() => parts,
+            Some(_) => return,
+            None => {
+                self.error_message = Some("unmatched quote in command".to_string());
+                return;
+            }
+        };
+
+        let home = env::var_os("HOME");
+        if self.dry_run {
+            self.print_dry_run(&parts, false, home.as_deref());
+            self.launched.store(true, Ordering::Relaxed);
+            return;
+        }
+
+        match output::CommandOutput::start(command, &parts, &self.config.env, home.as_ref(), &self.ctx) {
+            Ok(output) => {
+                self.output = Some(output);
+                self.launched.store(true, Ordering::Relaxed);
+                self.record_history();
+            }
+            Err(e) => self.error_message = Some(format!("failed to launch: {}: {}", parts[0], e)),
+        }
+    }
+
+    /// Resolves how to wrap a command in a terminal: the configured prefix,
+    /// then `$TERMINAL -e`, then the first common emulator that is installed.
+    fn terminal_command(&self) -> Option<Vec<String>> {
+        if let Some(terminal) = &self.config.terminal {
+            return Some(terminal.split_whitespace().map(String::from).collect());
+        }
+
+        if let Ok(terminal) = env::var("TERMINAL") {
+            if !terminal.is_empty() {
+                return Some(vec![terminal, "-e".to_string()]);
+            }
+        }
+
+        let known: [(&str, &[&str]); 8] = [
+            ("alacritty", &["-e"]),
+            ("kitty", &[]),
+            ("foot", &[]),
+            ("wezterm", &["start", "--"]),
+            ("konsole", &["-e"]),
+            ("gnome-terminal", &["--"]),
+            ("xfce4-terminal", &["-x"]),
+            ("xterm", &["-e"]),
+        ];
+
+        known
+            .iter()
+            .find(|(bin, _)| self.core.all_executables.iter().any(|c| (c.exec.is_none() || c.path.is_some()) && c.name == *bin))
+            .map(|(bin, args)| {
+                std::iter::once(*bin).chain(args.iter().copied()).map(String::from).collect()
+            })
+    }
+
+    fn spawn_process(&self, cmd_str: &str, is_sudo: bool, in_terminal: bool, password: Option<Zeroizing<String>>) {
+        let mut parts = match shell::command_parts(cmd_str) {
+            Some(parts) if !parts.is_empty() => parts,
+            Some(_) => return,
+            None => {
+                let _ = self.launch_tx.send(LaunchEvent::Failed("unmatched quote in command".to_string()));
+                return;
+            }
+        };
+
+        let terminal = if in_terminal {
+            let terminal = self.terminal_command();
+            if terminal.is_none() {
+                eprintln!("deemenu: no terminal emulator found, running '{}' directly", cmd_str);
+            }
+            terminal
+        } else {
+            None
+        };
+
+        // Sudo runs the command as-is, so the terminal and launcher only wrap normal launches
+        if let (false, Some(terminal)) = (is_sudo, terminal) {
+            parts.splice(0..0, terminal);
+        }
+        if !is_sudo {
+            parts.splice(0..0, self.config.launcher.prefix().iter().map(|arg| arg.to_string()));
+        }
+
+        let home = env::var_os("HOME");
+        log::debug!("spawning {:?} (sudo = {})", parts, is_sudo);
+
+        if self.print_command {
+            println!("{}", self.printed_command(parts, is_sudo));
+            let _ = self.launch_tx.send(LaunchEvent::Launched);
+            self.ctx.request_repaint();
+            return;
+        }
+
+        if self.dry_run {
+            self.print_dry_run(&parts, is_sudo, home.as_deref());
+            let _ = self.launch_tx.send(LaunchEvent::Launched);
+            self.ctx.request_repaint();
+            return;
+        }
+
+        let tx = self.launch_tx.clone();
+        let ctx = self.ctx.clone();
+        let env_vars = self.config.env.clone();
+        let notify = self.config.notify;
 
         thread::spawn(move || {
-            if is_sudo {
+            let event = if is_sudo {
                 // Sudo pipe execution
-                let parts: Vec<&str> = cmd_str.split_whitespace().collect();
-                if parts.is_empty() { return; }
-
-                let mut child = Command::new("sudo")
-                    .arg("-S") // Read stdin
-                    .arg("-k") // Ignore cache
-                    .arg("--")
-                    .args(parts)
-                    .stdin(Stdio::piped())
-                    .spawn()
-                    .expect("Failed to spawn sudo");
-
-                if let Some(mut stdin) = child.stdin.take() {
-                    if let Some(pw) = password {
-                        let _ = stdin.write_all(pw.as_bytes());
+                let password = password.unwrap_or_default();
+
+                // Check the password with a no-op first, so a typo can be retried
+                // instead of the real command silently failing.
+                let check = run_sudo(&["true"], &password, None, Stdio::piped())
+                    .and_then(|child| child.wait_with_output());
+                let result = match check {
+                    Ok(output) if output.status.success() => {
+                        run_sudo(&parts, &password, home.as_deref(), Stdio::null()).map(Ok)
+                    }
+                    Ok(output) => Ok(Err(sudo_failure(&output))),
+                    Err(e) => Err(e),
+                };
+
+                // Both sudo processes have been handed the password; wipe our copy now
+                drop(password);
+
+                match result {
+                    Ok(Ok(mut child)) => {
+                        let _ = tx.send(LaunchEvent::Launched);
+                        ctx.request_repaint();
+
+                        // Reap the child so it doesn't linger as a zombie
+                        let _ = child.wait();
+                        return;
                     }
+                    Ok(Err(event)) => event,
+                    Err(e) => LaunchEvent::Failed(format!("sudo: {}", e)),
                 }
             } else {
                 // Normal execution
-                let parts: Vec<&str> = cmd_str.split_whitespace().collect();
-                if let Some((cmd, args)) = parts.split_first() {
-                    let _ = Command::new(cmd)
-                        .args(args)
-                        .spawn();
+                let Some((cmd, args)) = parts.split_first() else { return };
+
+                let mut command = Command::new(cmd);
+                command
+                    .args(args)
+                    .envs(&env_vars)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .process_group(0); // Own process group, so it outlives us cleanly
+                if let Some(home) = &home {
+                    command.current_dir(home);
+                }
+
+                match command.spawn() {
+                    Ok(mut child) => {
+                        let _ = tx.send(LaunchEvent::Launched);
+                        ctx.request_repaint();
+
+                        // Reap the child so it doesn't linger as a zombie
+                        let _ = child.wait();
+                        return;
+                    }
+                    Err(e) => LaunchEvent::Failed(format!("{}: {}", cmd, e)),
                 }
+            };
+
+            // The window may be gone by the time a launch fails, so also tell the desktop
+            if let LaunchEvent::Failed(reason) = &event {
+                log::warn!("launch failed: {}", reason);
+                notify_failure(notify, reason);
             }
+
+            let _ = tx.send(event);
+            ctx.request_repaint();
         });
     }
+
+    /// `--print-command`: the command line that runs `parts`, with sudo or the configured environment in front.
+    fn printed_command(&self, parts: Vec<String>, is_sudo: bool) -> String {
+        let mut env_vars: Vec<_> = self.config.env.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        env_vars.sort();
+
+        let prefix = match is_sudo {
+            true => vec!["sudo".to_string()],
+            false if env_vars.is_empty() => Vec::new(),
+            false => std::iter::once("env".to_string()).chain(env_vars).collect(),
+        };
+        shell::join(&prefix.into_iter().chain(parts).collect::<Vec<_>>())
+    }
+
+    /// `--dry-run`: reports what `spawn_process` would execute instead of running it.
+    fn print_dry_run(&self, parts: &[String], is_sudo: bool, home: Option<&OsStr>) {
+        let cwd = home.map(Path::new).map_or("(unchanged)".into(), Path::to_string_lossy);
+
+        if is_sudo {
+            let sudo = ["sudo", "-S", "-k", "-p", "", "--"].map(String::from);
+            eprintln!("deemenu: dry run: {:?}", sudo.iter().chain(parts).collect::<Vec<_>>());
+            eprintln!("deemenu: dry run: password on stdin, cwd {}", cwd);
+            return;
+        }
+
+        eprintln!("deemenu: dry run: {:?}", parts);
+        eprintln!("deemenu: dry run: cwd {}", cwd);
+        let mut env_vars: Vec<_> = self.config.env.iter().collect();
+        env_vars.sort();
+        for (key, value) in env_vars {
+            eprintln!("deemenu: dry run: env {}={}", key, value);
+        }
+    }
+
+    /// Single-instance mode: answers other launches by coming back to the front.
+    fn listen_for_raises(&mut self, listener: UnixListener) {
+        let (tx, rx) = mpsc::channel();
+        self.raise_rx = Some(rx);
+
+        let ctx = self.ctx.clone();
+        instance::serve(listener, move || {
+            let _ = tx.send(());
+            ctx.request_repaint();
+        });
+    }
+
+    /// Handles pending raise requests: always back in search mode with no
+    /// sudo state left over, and with an empty query unless `clear_on_open`
+    /// is off. Then takes focus again.
+    fn drain_raises(&mut self) {
+        let Some(rx) = &self.raise_rx else { return };
+        if rx.try_iter().count() == 0 {
+            return;
+        }
+        self.reopen();
+        self.raise_window = true;
+        self.focus_input = true;
+        self.ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+    }
+
+    /// The state a raised window starts from, as `drain_raises` describes.
+    fn reopen(&mut self) {
+        self.core.mode = AppMode::Search;
+        self.core.pending_action = None;
+        self.password_query.zeroize();
+        self.core.pending_sudo_command.clear();
+        self.password_error = false;
+        self.error_message = None;
+        self.core.marked.clear();
+        self.core.open_with = None;
+        self.core.sudo_selection = false;
+
+        if self.config.clear_on_open {
+            self.core.search_query.clear();
+            self.core.typed_query = None;
+            self.core.selected_index = 0;
+        }
+        move_cursor_to_end(&self.ctx, egui::Id::new("search_query"), &self.core.search_query);
+        self.core.update_filter();
+    }
+
+    /// Counts the launch `attempt_run` started, now that it has. Cancelled
+    /// confirmations, wrong passwords and failed spawns never get here.
+    fn record_history(&mut self) {
+        let Some((names, invocation)) = self.pending_history.take() else { return };
+        if !self.config.history || self.dry_run {
+            return;
+        }
+        // A suggested command line is counted with the command lines only
+        names.iter().filter(|name| invocation.as_ref() != Some(*name)).for_each(|name| history::record(name));
+        invocation.iter().for_each(|command| history::record_command(command));
+    }
+
+    /// Applies launch results reported by spawn threads.
+    /// Returns true once something has actually started and the window can close.
+    fn drain_launches(&mut self) -> bool {
+        let mut reported = false;
+
+        while let Ok(event) = self.launch_rx.try_recv() {
+            reported = true;
+            self.launching = self.launching.saturating_sub(1);
+            match event {
+                LaunchEvent::Launched => {
+                    self.launched.store(true, Ordering::Relaxed);
+                    self.batch_started = true;
+                    self.record_history();
+                }
+                LaunchEvent::Failed(reason) => {
+                    self.error_message = Some(format!("failed to launch: {}", reason));
+                }
+                LaunchEvent::WrongPassword => {
+                    self.password_query.zeroize();
+                    self.password_error = true;
+                }
+            }
+        }
+
+        // Closing any earlier would end the process before the rest of a batch started
+        reported && self.launching == 0 && std::mem::take(&mut self.batch_started)
+    }
+}
+
+/// Starts `sudo -S -k -- <args>` and feeds it the password on stdin.
+fn run_sudo<S: AsRef<OsStr>>(args: &[S], password: &str, cwd: Option<&OsStr>, stderr: Stdio) -> std::io::Result<Child> {
+    let mut command = Command::new("sudo");
+    command
+        .arg("-S") // Read stdin
+        .arg("-k") // Ignore cache
+        .args(["-p", ""]) // No prompt text on stderr
+        .arg("--")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(stderr)
+        .process_group(0);
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+
+    let mut child = command.spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // sudo -S reads a whole line, so terminate it, then close stdin (drop) to signal EOF
+        stdin.write_all(password.as_bytes())?;
+        stdin.write_all(b"\n")?;
+        stdin.flush()?;
+    }
+
+    Ok(child)
+}
+
+/// Turns a failed `sudo true` check into something to show the user:
+/// a retry for a bad password, or sudo's own complaint (e.g. not in sudoers).
+fn sudo_failure(output: &Output) -> LaunchEvent {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if stderr.contains("incorrect password") || stderr.contains("try again") {
+        return LaunchEvent::WrongPassword;
+    }
+
+    match stderr.lines().map(str::trim).rev().find(|l| !l.is_empty()) {
+        Some(reason) => LaunchEvent::Failed(reason.to_string()),
+        None => LaunchEvent::Failed(format!("sudo exited with {}", output.status)),
+    }
+}
+
+/// Puts the text cursor after the last character, e.g. after text was appended programmatically.
+fn move_cursor_to_end(ctx: &egui::Context, id: egui::Id, text: &str) {
+    let mut state = egui::TextEdit::load_state(ctx, id).unwrap_or_default();
+    let end = egui::text::CCursor::new(text.chars().count());
+    state.cursor.set_char_range(Some(egui::text::CCursorRange::one(end)));
+    state.store(ctx, id);
+}
+
+/// Shown when `copy_to_clipboard` had to fall back to stdout
+const CLIPBOARD_FAILED: &str = "couldn't access the clipboard, printed to stdout instead";
+
+/// Copies `text`, or prints it to stdout when there is no clipboard to use
+/// (e.g. a bare X or Wayland session). Returns whether it was copied.
+fn copy_to_clipboard(text: &str) -> bool {
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text)) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("deemenu: failed to copy to clipboard: {}", e);
+            println!("{}", text);
+            false
+        }
+    }
+}
+
+/// Reports a failed launch as a desktop notification, if enabled.
+fn notify_failure(notify: Notify, reason: &str) {
+    match notify {
+        Notify::None => {}
+        Notify::NotifySend => {
+            let status = Command::new("notify-send")
+                .args(["--app-name=DeeMenu", "--urgency=critical", "DeeMenu: launch failed", reason])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            if let Err(e) = status {
+                eprintln!("deemenu: can't run notify-send: {}", e);
+            }
+        }
+    }
+}
+
+impl DeeMenu {
+    /// The input line: search box (plus the result strip, if `with_results`),
+    /// the password prompt or the confirmation question. Returns a click on a result.
+    fn show_input_row(&mut self, ui: &mut egui::Ui, with_results: bool) -> Option<(usize, ResultClick)> {
+        ui.style_mut().spacing.item_spacing = egui::vec2(8.0, 0.0);
+        ui.add_space(5.0);
+
+        match self.core.mode {
+            // SEARCH MODE
+            AppMode::Search => {
+                self.show_search_box(ui);
+                if with_results {
+                    return self.show_results(ui);
+                }
+            }
+
+            // PASSWORD MODE
+            AppMode::SudoPassword => self.show_password_box(ui),
+
+            // CONFIRM MODE
+            AppMode::Confirm => self.show_confirm_prompt(ui),
+        }
+        None
+    }
+
+    /// Window height for the vertical layout: the input row plus one row per
+    /// result, capped at `lines` rows.
+    fn desired_height(&self) -> f32 {
+        if self.output.is_some() {
+            return self.config.height * (self.config.lines + 1) as f32;
+        }
+        if self.config.layout == Layout::Horizontal {
+            return self.config.height;
+        }
+
+        let rows = match self.core.mode {
+            AppMode::Search if self.shows_no_matches() => 1,
+            AppMode::Search => self.core.filtered_executables.len().min(self.config.lines).min(self.config.max_results.max(1)),
+            AppMode::SudoPassword | AppMode::Confirm => 0,
+        };
+        self.config.height * (rows + 1) as f32
+    }
+
+    fn show_search_box(&mut self, ui: &mut egui::Ui) {
+        let font_id = egui::FontId::new(self.config.font_size, egui::FontFamily::Monospace);
+
+        if let Some(open_with) = &self.core.open_with {
+            let file = open_with.path.file_name().unwrap_or_default().to_string_lossy();
+            ui.label(egui::RichText::new(format!("open {} with", file)).color(self.config.accent()).strong());
+        } else if !self.config.prompt.is_empty() {
+            ui.label(egui::RichText::new(&self.config.prompt).color(self.config.accent()).strong());
+        }
+
+        let text_width = ui.fonts(|f| {
+            f.layout_no_wrap(self.core.search_query.clone(), font_id, self.config.text()).rect.width()
+        });
+        let box_width = (text_width + 20.0).max(100.0);
+
+        self.claim_focus(ui, egui::Id::new("search_query"));
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut self.core.search_query)
+                .id(egui::Id::new("search_query"))
+                .hint_text(self.config.hint_text.as_str())
+                .frame(false)
+                .desired_width(box_width)
+        );
+
+        self.keep_focus(&response);
+
+        if response.changed() {
+            self.core.query_edited();
+            self.error_message = None;
+        }
+
+        if !self.config.separator.is_empty() {
+            ui.label(egui::RichText::new(&self.config.separator).color(self.config.separator()));
+        }
+
+        // Until the first results arrive; the spinner keeps requesting repaints itself
+        if self.is_scanning() {
+            ui.add(egui::Spinner::new().size(self.config.font_size).color(egui::Color32::GRAY));
+            ui.label(egui::RichText::new("scanning…").color(egui::Color32::GRAY));
+        }
+
+        if let Some(error) = &self.error_message {
+            ui.label(egui::RichText::new(error).color(egui::Color32::from_rgb(255, 100, 100)));
+        }
+
+        if let Some(url) = self.core.web_target(&self.config.search_url) {
+            ui.label(egui::RichText::new(format!("open {}", url)).color(egui::Color32::GRAY));
+        }
+
+        let calc_expr = self.core.search_query.trim_start().strip_prefix('=').unwrap_or("");
+        if self.core.is_calc_query() && self.core.filtered_executables.is_empty() && !calc_expr.trim().is_empty() {
+            ui.label(egui::RichText::new("invalid expression").color(egui::Color32::from_rgb(255, 100, 100)));
+        }
+        // The scratchpad: earlier results, latest first
+        if self.core.is_calc_query() {
+            for (expr, result) in self.core.calculations.iter().rev() {
+                ui.label(egui::RichText::new(format!("{} = {}", expr, result)).color(egui::Color32::GRAY));
+            }
+        }
+    }
+
+    /// Whether nothing is listed yet because the scan is still running.
+    fn is_scanning(&self) -> bool {
+        self.scan_rx.is_some() && self.core.filtered_executables.is_empty()
+    }
+
+    /// Whether to say that nothing matched: something was typed (or apps for a file asked for), and neither
+    /// the calculator, a web target nor a running scan already explains the empty list.
+    fn shows_no_matches(&self) -> bool {
+        self.core.filtered_executables.is_empty()
+            && !self.is_scanning()
+            && (!self.core.search_query.trim().is_empty() || self.core.open_with.is_some())
+            && !self.core.is_calc_query()
+            && self.core.web_target(&self.config.search_url).is_none()
+    }
+
+    /// Draws the result pills (a strip or a list, depending on the layout).
+    /// Returns the index of a clicked result and what the click asked for.
+    fn show_results(&self, ui: &mut egui::Ui) -> Option<(usize, ResultClick)> {
+        let vertical = self.config.layout == Layout::Vertical;
+
+        if self.shows_no_matches() {
+            let placeholder = egui::RichText::new("no matches").color(egui::Color32::GRAY).italics();
+            if vertical {
+                // Indented like the text in a result row
+                let row_size = egui::vec2(ui.available_width(), self.config.height);
+                ui.allocate_ui_with_layout(row_size, egui::Layout::left_to_right(egui::Align::Center), |ui| {
+                    ui.add_space(self.config.inset());
+                    ui.label(placeholder);
+                });
+            } else {
+                ui.label(placeholder);
+            }
+            return None;
+        }
+
+        // Store click result to process outside loop
+        let mut clicked = None;
+        // Copied results and dmenu choices aren't commands to run with sudo or in a terminal
+        let runs_commands = !self.dmenu && !self.core.copies_selection();
+        // Unknown focus (some backends never say) counts as focused
+        let dimmed = self.config.dim_unfocused && ui.input(|i| i.viewport().focused) == Some(false);
+
+        let mut scroll_area = if vertical {
+            egui::ScrollArea::vertical()
+        } else {
+            egui::ScrollArea::horizontal()
+        };
+
+        // Follow the highlight only when it moves, so the wheel can skim freely in between
+        let follow_id = egui::Id::new("followed_selection");
+        let followed = (self.core.selected_index, self.core.search_query.clone());
+        let previous = ui.data_mut(|d| d.get_temp::<(usize, String)>(follow_id));
+        let follow = previous.as_ref() != Some(&followed);
+        // A new query starts over from the first result, wherever the old ones were scrolled to
+        if previous.is_some_and(|(_, query)| query != followed.1) {
+            scroll_area = scroll_area.scroll_offset(egui::Vec2::ZERO);
+        }
+        ui.data_mut(|d| d.insert_temp(follow_id, followed));
+
+        let galleys_id = egui::Id::new("name_galleys");
+        let mut galleys: NameGalleys = ui.data_mut(|d| std::mem::take(d.get_temp_mut_or_default::<NameGalleys>(galleys_id)));
+        if galleys.len() > MAX_NAME_GALLEYS {
+            galleys.clear();
+        }
+
+        scroll_area.show(ui, |ui| {
+            let page_size = self.config.max_results.max(1);
+            let page = self.core.filtered_executables.iter().enumerate().skip(self.core.page_start(page_size)).take(page_size);
+
+            for (i, candidate) in page {
+                let is_selected = i == self.core.selected_index;
+
+                // Unselected pills show the panel through, so translucency isn't applied twice
+                let bg_color = if is_selected && self.core.sudo_selection {
+                    self.config.with_opacity(egui::Color32::from_rgb(190, 40, 40))
+                } else if is_selected {
+                    self.config.with_opacity(self.config.accent())
+                } else {
+                    egui::Color32::TRANSPARENT
+                };
+                let bg_color = if is_selected && dimmed {
+                    bg_color.lerp_to_gamma(self.config.with_opacity(self.config.panel()), 0.5)
+                } else {
+                    bg_color
+                };
+
+                let text_color = if is_selected {
+                    self.config.text()
+                } else {
+                    egui::Color32::from_rgb(171, 178, 191)
+                };
+
+                let cut = ellipsis_cut(&candidate.name, self.config.max_name_length);
+                let key = (candidate.name.clone(), is_selected, candidate.matched.clone(), self.core.is_marked(candidate), self.core.is_pinned(candidate));
+                let mut layout = |ui: &egui::Ui| {
+                    let galley = galleys.entry(key.clone()).or_insert_with(|| ui.painter().layout_job(self.highlighted_name(candidate, cut, is_selected, text_color)));
+                    Arc::clone(galley)
+                };
+                // Icons are square, a bit taller than the text
+                let icon_size = |galley: &egui::Galley| galley.size().y * 1.25;
+                let icon_width = |galley: &egui::Galley| if candidate.icon.is_some() { icon_size(galley) + 6.0 } else { 0.0 };
+
+                // Vertical rows have a fixed size, so rows scrolled out of view are never laid out.
+                // Strip pills need their text's width, which `galleys` has once a name was seen.
+                let galley = (!vertical).then(|| layout(ui));
+                let rect_size = match &galley {
+                    Some(galley) => galley.size() + egui::Vec2::from(self.config.padding) + egui::vec2(icon_width(galley), 0.0),
+                    None => egui::vec2(ui.available_width(), self.config.height),
+                };
+                let (rect, mut resp) = ui.allocate_at_least(rect_size, egui::Sense::click());
+                if cut.is_some() {
+                    resp = resp.on_hover_text(&candidate.name);
+                }
+
+                if resp.clicked() {
+                    let mark = ui.input(|i| i.modifiers.command) && runs_commands;
+                    clicked = Some((i, if mark { ResultClick::Mark } else { ResultClick::Run }));
+                }
+                if resp.middle_clicked() && runs_commands {
+                    clicked = Some((i, ResultClick::Terminal));
+                }
+                resp.context_menu(|ui| {
+                    let mut items = vec![("Run", ResultClick::Run)];
+                    if runs_commands {
+                        items.extend([("Run as sudo", ResultClick::Sudo), ("Run in terminal", ResultClick::Terminal)]);
+                    }
+                    items.push(("Copy name", ResultClick::Copy));
+
+                    for (label, click) in items {
+                        if ui.button(label).clicked() {
+                            clicked = Some((i, click));
+                            ui.close_menu();
+                        }
+                    }
+                });
+
+                if is_selected && follow {
+                    ui.scroll_to_rect(rect, Some(egui::Align::Center));
+                }
+
+                if !ui.is_rect_visible(rect) {
+                    continue;
+                }
+                let galley = galley.unwrap_or_else(|| layout(ui));
+
+                ui.painter().rect_filled(rect, self.config.corner_radius, bg_color);
+
+                if let Some(icon) = &candidate.icon {
+                    let icon_size = icon_size(&galley);
+                    let icon_rect = egui::Rect::from_min_size(
+                        rect.min + egui::vec2(self.config.inset(), (rect.height() - icon_size) / 2.0),
+                        egui::Vec2::splat(icon_size),
+                    );
+                    egui::Image::new(format!("file://{}", icon.display())).paint_at(ui, icon_rect);
+                }
+
+                let text_pos = rect.min + egui::vec2(self.config.inset() + icon_width(&galley), (rect.height() - galley.size().y) / 2.0);
+                ui.painter().galley(text_pos, galley, egui::Color32::PLACEHOLDER);
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(galleys_id, galleys));
+
+        clicked
+    }
+
+    /// The candidate's name with the matched part in the accent color, or
+    /// underlined on the highlighted entry where the accent is the background.
+    /// A name cut at `cut` (see `ellipsis_cut`) ends in an ellipsis; pinned ones start with a star.
+    fn highlighted_name(&self, candidate: &Candidate, cut: Option<usize>, is_selected: bool, text_color: egui::Color32) -> egui::text::LayoutJob {
+        let plain = egui::TextFormat {
+            font_id: egui::FontId::new(self.config.font_size, egui::FontFamily::Monospace),
+            color: text_color,
+            ..Default::default()
+        };
+        let highlight = if is_selected {
+            egui::TextFormat { underline: egui::Stroke::new(1.0, text_color), ..plain.clone() }
+        } else {
+            egui::TextFormat { color: self.config.accent(), ..plain.clone() }
+        };
+
+        let name = &candidate.name[..cut.unwrap_or(candidate.name.len())];
+        let range = candidate.matched.clone().filter(|r| candidate.name.get(r.clone()).is_some()).unwrap_or(0..0);
+        let range = range.start.min(name.len())..range.end.min(name.len());
+        let mut job = egui::text::LayoutJob::default();
+        if self.core.is_marked(candidate) {
+            let color = if is_selected { text_color } else { self.config.accent() };
+            job.append("✔ ", 0.0, egui::TextFormat { color, ..plain.clone() });
+        }
+        if self.core.is_pinned(candidate) {
+            let color = if is_selected { text_color } else { self.config.accent() };
+            job.append("★ ", 0.0, egui::TextFormat { color, ..plain.clone() });
+        }
+        job.append(&name[..range.start], 0.0, plain.clone());
+        job.append(&name[range.clone()], 0.0, highlight);
+        job.append(&name[range.end..], 0.0, plain.clone());
+        if cut.is_some() {
+            job.append("…", 0.0, plain);
+        }
+        job
+    }
+
+    /// `close_on_focus_loss`: closes once the window loses the focus it had.
+    /// Startup (before the window manager first focuses us) and the sudo
+    /// password prompt are left alone so a stray blip can't lose the command.
+    fn close_on_focus_loss(&mut self, ctx: &egui::Context) {
+        match ctx.input(|i| i.viewport().focused) {
+            Some(true) => self.was_focused = true,
+            Some(false) if self.config.close_on_focus_loss && self.was_focused && self.core.mode == AppMode::Search => {
+                self.was_focused = false;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+            _ => {}
+        }
+    }
+
+    /// Focuses the input box `id` once when asked to, before it is added so it
+    /// already takes this frame's keys (the first one typed after switching to
+    /// the password prompt included).
+    fn claim_focus(&mut self, ui: &egui::Ui, id: egui::Id) {
+        if std::mem::take(&mut self.focus_input) {
+            ui.memory_mut(|memory| memory.request_focus(id));
+        }
+    }
+
+    /// Asks for focus again only if the input box lost it (e.g. a click on the
+    /// panel), so typing always lands in it.
+    fn keep_focus(&mut self, response: &egui::Response) {
+        if response.lost_focus() {
+            self.focus_input = true;
+            self.ctx.request_repaint();
+        }
+    }
+
+    /// The output panel of a Shift+Enter command, following new lines as they arrive.
+    fn show_output(&self, ui: &mut egui::Ui) {
+        let Some(output) = &self.output else { return };
+        let font_id = egui::FontId::new(self.config.font_size, egui::FontFamily::Monospace);
+        let dim = egui::Color32::GRAY;
+
+        ui.add_space(4.0);
+        let status = output.status.as_deref().unwrap_or("running");
+        ui.label(egui::RichText::new(format!("$ {} ({}, Esc to dismiss)", output.command, status)).font(font_id.clone()).color(dim));
+
+        egui::ScrollArea::vertical()
+            .stick_to_bottom(true)
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                for line in &output.lines {
+                    ui.label(egui::RichText::new(line).font(font_id.clone()));
+                }
+            });
+    }
+
+    /// `[confirm]`: asks before carrying out the held back command.
+    fn show_confirm_prompt(&self, ui: &mut egui::Ui) {
+        let command = match &self.core.pending_action {
+            Some((Action::Sudo(command), _)) => format!("sudo {}", command),
+            Some((Action::Launch { command, .. }, _)) => command.clone(),
+            Some((Action::Batch(actions), _)) => actions.iter()
+                .filter_map(|action| match action {
+                    Action::Launch { command, .. } => Some(command.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("', '"),
+            _ => String::new(),
+        };
+
+        ui.label(
+            egui::RichText::new(format!("Run '{}'?", command))
+                .color(egui::Color32::from_rgb(255, 190, 80))
+                .strong()
+        );
+        ui.label(egui::RichText::new("[Enter to confirm / Esc to cancel]").color(egui::Color32::GRAY));
+    }
+
+    fn show_password_box(&mut self, ui: &mut egui::Ui) {
+        ui.label(
+            egui::RichText::new("🔒 SUDO PASSWORD:")
+                .color(egui::Color32::from_rgb(255, 100, 100))
+                .strong()
+        );
+
+        self.claim_focus(ui, egui::Id::new("password_query"));
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut *self.password_query)
+                .id(egui::Id::new("password_query"))
+                .password(true)
+                .frame(false)
+                .desired_width(200.0)
+        );
+
+        self.keep_focus(&response);
+        if response.changed() {
+            self.password_error = false;
+        }
+
+        ui.label(egui::RichText::new(format!("for '{}'", self.core.pending_sudo_command)).italics());
+
+        if self.password_error {
+            ui.label(
+                egui::RichText::new("wrong password, try again")
+                    .color(egui::Color32::from_rgb(255, 100, 100))
+            );
+        } else if let Some(error) = &self.error_message {
+            ui.label(egui::RichText::new(error).color(egui::Color32::from_rgb(255, 100, 100)));
+        }
+    }
+
 }
 
 impl eframe::App for DeeMenu {
+    fn clear_color(&self, visuals: &egui::Visuals) -> [f32; 4] {
+        if self.config.is_transparent() {
+            // Let the translucent panel show the desktop (and compositor blur) behind it
+            egui::Rgba::TRANSPARENT.to_array()
+        } else {
+            visuals.panel_fill.to_normalized_gamma_f32()
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // --- Startup Positioning Fix ---
-        if self.startup_counter < 3 {
-            ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(0.0, 0.0)));
+        // --- Window Placement & Focus ---
+        if let Some(size) = ctx.input(|i| i.viewport().monitor_size) {
+            self.monitor_width = Some(size.x);
+        }
+        if !self.positioned {
+            // Without xrandr info, place against whatever monitor egui reports once it knows
+            if let Some(size) = ctx.input(|i| i.viewport().monitor_size) {
+                let monitor = egui::Rect::from_min_size(egui::Pos2::ZERO, size);
+                ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(self.config.window_position(Some(monitor))));
+                self.positioned = true;
+            }
+        }
+        if std::mem::take(&mut self.raise_window) {
             ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
-            self.startup_counter += 1;
-            ctx.request_repaint();
         }
+        self.close_on_focus_loss(ctx);
+
+        // --- Background Scan / Launch Results ---
+        self.drain_scan();
+        self.drain_raises();
+        let launched = self.drain_launches();
 
         // --- Input ---
-        let esc_pressed = ctx.input(|i| i.key_pressed(egui::Key::Escape));
-        let enter_pressed = ctx.input(|i| i.key_pressed(egui::Key::Enter));
-        let tab_pressed = ctx.input(|i| i.key_pressed(egui::Key::Tab));
-        let arrow_right = ctx.input(|i| i.key_pressed(egui::Key::ArrowRight));
-        let arrow_left = ctx.input(|i| i.key_pressed(egui::Key::ArrowLeft));
+        let keys = &self.config.keys;
+        let (next_keys, prev_keys) = keys.navigation(self.config.layout);
+        let esc_pressed = ctx.input_mut(|i| keys.close.pressed(i));
+        let enter_pressed = ctx.input_mut(|i| keys.run.pressed(i));
+        let attached_pressed = ctx.input_mut(|i| keys.run_attached.pressed(i));
+        let tab_pressed = ctx.input_mut(|i| keys.complete.pressed(i));
+        let arrow_next = ctx.input_mut(|i| next_keys.pressed(i));
+        let arrow_prev = ctx.input_mut(|i| prev_keys.pressed(i));
+        let (first_pressed, last_pressed) = (ctx.input_mut(|i| keys.first.taken(i)), ctx.input_mut(|i| keys.last.taken(i)));
+        let (page_up, page_down) = (ctx.input_mut(|i| keys.page_up.taken(i)), ctx.input_mut(|i| keys.page_down.taken(i)));
+        let sudo_pressed = ctx.input_mut(|i| keys.sudo.pressed(i));
+        let mark_pressed = ctx.input_mut(|i| keys.mark.taken(i));
+        let quick_digit = ctx.input(|i| {
+            QUICK_PICK_KEYS.iter().position(|key| i.key_pressed(*key) && i.modifiers.matches_exact(egui::Modifiers::ALT))
+        });
+
+        // Ctrl+V: take the paste events ourselves rather than leaving them to the
+        // TextEdit, so pasting still lands while focus is being re-requested.
+        let pasted = ctx.input_mut(|i| {
+            let mut text = String::new();
+            i.events.retain(|event| match event {
+                egui::Event::Paste(t) => {
+                    text.push_str(t);
+                    false
+                }
+                _ => true,
+            });
+            text
+        });
+
+        if !pasted.is_empty() {
+            // Single line box: fold newlines into spaces
+            let pasted = pasted.trim_end_matches(['\r', '\n']).replace(['\r', '\n'], " ");
+            match self.core.mode {
+                AppMode::Search => {
+                    self.core.search_query.push_str(&pasted);
+                    self.core.query_edited();
+                    move_cursor_to_end(ctx, egui::Id::new("search_query"), &self.core.search_query);
+                    self.error_message = None;
+                }
+                AppMode::SudoPassword => {
+                    self.password_query.push_str(&pasted);
+                    move_cursor_to_end(ctx, egui::Id::new("password_query"), &self.password_query);
+                    self.password_error = false;
+                }
+                AppMode::Confirm => {}
+            }
+        }
+
+        if let Some(output) = &mut self.output {
+            output.drain();
+        }
 
         if esc_pressed {
-            if self.mode == AppMode::SudoPassword {
-                self.mode = AppMode::Search;
-                self.password_query.clear();
+            if self.output.is_some() {
+                // First Esc only dismisses the output panel
+                self.output = None;
+            } else if self.core.mode == AppMode::SudoPassword {
+                self.core.mode = AppMode::Search;
+                self.focus_input = true;
+                self.password_query.zeroize();
+                self.password_error = false;
+            } else if self.core.mode == AppMode::Confirm {
+                self.core.cancel_confirmation();
+                self.focus_input = true;
+            } else if self.core.restore_typed_query() || self.core.cancel_open_with() {
+                // Esc after Tab completion returns to the typed text before closing,
+                // and from the "open with" apps to the file
+                move_cursor_to_end(ctx, egui::Id::new("search_query"), &self.core.search_query);
             } else {
                 ctx.send_viewport_cmd(egui::ViewportCommand::Close);
             }
         }
 
         // Navigation (Search Mode Only)
-        if self.mode == AppMode::Search && !self.filtered_executables.is_empty() {
-            if arrow_right || tab_pressed {
-                self.selected_index = (self.selected_index + 1) % self.filtered_executables.len();
-            }
-            if arrow_left {
-                if self.selected_index == 0 {
-                    self.selected_index = self.filtered_executables.len() - 1;
+        if self.core.mode == AppMode::Search && !self.core.filtered_executables.is_empty() {
+            // Tab completes to the highlight first; once completed, it cycles
+            if tab_pressed {
+                if self.core.complete_selection() {
+                    move_cursor_to_end(ctx, egui::Id::new("search_query"), &self.core.search_query);
                 } else {
-                    self.selected_index -= 1;
+                    self.core.select_next();
                 }
             }
+            if arrow_next {
+                self.core.select_next();
+            }
+            if arrow_prev {
+                self.core.select_previous();
+            }
+            // Jumps clamp at the ends rather than wrapping like the arrows
+            let last = self.core.filtered_executables.len() - 1;
+            let page = self.config.page_size.max(1);
+            if first_pressed {
+                self.core.selected_index = 0;
+            }
+            if last_pressed {
+                self.core.selected_index = last;
+            }
+            if page_up {
+                self.core.selected_index = self.core.selected_index.saturating_sub(page);
+            }
+            if page_down {
+                self.core.selected_index = (self.core.selected_index + page).min(last);
+            }
+            if self.config.scroll_selects {
+                self.scroll_selection(ctx);
+            }
+            // Copying and printing choices have nothing to run with sudo
+            if sudo_pressed && !self.dmenu && !self.core.copies_selection() {
+                self.core.toggle_sudo();
+            }
+            if mark_pressed && !self.dmenu {
+                self.core.toggle_mark();
+            }
         }
 
-        let mut should_close = false;
+        let mut should_close = launched;
 
         // --- UI Rendering ---
-        let panel_color = match self.mode {
-            AppMode::Search => egui::Color32::from_rgb(35, 36, 41),
-            AppMode::SudoPassword => egui::Color32::from_rgb(60, 20, 20),
+        let panel_color = match self.core.mode {
+            AppMode::Search => self.config.panel(),
+            AppMode::SudoPassword if self.password_error => self.config.with_opacity(egui::Color32::from_rgb(110, 20, 20)),
+            AppMode::SudoPassword => self.config.with_opacity(egui::Color32::from_rgb(60, 20, 20)),
+            AppMode::Confirm => self.config.with_opacity(egui::Color32::from_rgb(70, 50, 15)),
         };
 
-        egui::CentralPanel::default().frame(egui::Frame::none().fill(panel_color)).show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.style_mut().spacing.item_spacing = egui::vec2(8.0, 0.0);
-                ui.add_space(5.0);
-
-                match self.mode {
-                    // SEARCH MODE
-                    AppMode::Search => {
-                        let font_id = egui::FontId::new(14.0, egui::FontFamily::Monospace);
-
-                        let text_width = ui.fonts(|f| {
-                            f.layout_no_wrap(self.search_query.clone(), font_id, egui::Color32::WHITE).rect.width()
-                        });
-                        let box_width = (text_width + 20.0).max(100.0);
-
-                        let response = ui.add(
-                            egui::TextEdit::singleline(&mut self.search_query)
-                                .hint_text("Run...")
-                                .frame(false)
-                                .desired_width(box_width)
-                        );
-
-                        if self.startup_counter < 3 || !ui.memory(|m| m.has_focus(response.id)) {
-                            response.request_focus();
-                        }
-
-                        if response.changed() {
-                            self.selected_index = 0;
-                            self.update_filter();
-                        }
+        let vertical = self.config.layout == Layout::Vertical;
+        let mut clicked = None;
 
-                        ui.label(egui::RichText::new("|").color(egui::Color32::GRAY));
-
-                        // Store click result to process outside loop
-                        let mut clicked_index = None;
-
-                        egui::ScrollArea::horizontal().show(ui, |ui| {
-                            for (i, name) in self.filtered_executables.iter().enumerate() {
-                                let is_selected = i == self.selected_index;
-
-                                let bg_color = if is_selected {
-                                    egui::Color32::from_rgb(217, 70, 239)
-                                } else {
-                                    panel_color
-                                };
-
-                                let text_color = if is_selected {
-                                    egui::Color32::WHITE
-                                } else {
-                                    egui::Color32::from_rgb(171, 178, 191)
-                                };
-
-                                let galley = ui.painter().layout_no_wrap(
-                                    name.clone(),
-                                    egui::FontId::new(14.0, egui::FontFamily::Monospace),
-                                    text_color
-                                );
-
-                                let padding = egui::vec2(12.0, 6.0);
-                                let rect_size = galley.size() + padding;
-                                let (rect, resp) = ui.allocate_at_least(rect_size, egui::Sense::click());
-
-                                ui.painter().rect_filled(rect, 2.0, bg_color);
-
-                                let text_pos = rect.min + egui::vec2(6.0, (rect.height() - galley.size().y) / 2.0);
-                                ui.painter().galley(text_pos, galley, egui::Color32::PLACEHOLDER);
-
-                                if resp.clicked() {
-                                    clicked_index = Some(i);
-                                }
-
-                                if is_selected {
-                                    ui.scroll_to_rect(rect, Some(egui::Align::Center));
-                                }
-                            }
-                        });
-
-                        // Handle mouse click
-                        if let Some(i) = clicked_index {
-                            self.selected_index = i;
-                            self.search_query = self.filtered_executables[i].clone();
-                            should_close = self.attempt_run();
-                        }
-                    }
+        egui::CentralPanel::default().frame(egui::Frame::none().fill(panel_color)).show(ctx, |ui| {
+            if vertical || self.output.is_some() {
+                // Input line is one row tall, results (or output) get the rows below it
+                let row_size = egui::vec2(ui.available_width(), self.config.height);
+                let row_layout = egui::Layout::left_to_right(egui::Align::Center);
+                ui.allocate_ui_with_layout(row_size, row_layout, |ui| {
+                    clicked = self.show_input_row(ui, !vertical);
+                });
 
-                    // PASSWORD MODE
-                    AppMode::SudoPassword => {
-                        ui.label(
-                            egui::RichText::new("🔒 SUDO PASSWORD:")
-                                .color(egui::Color32::from_rgb(255, 100, 100))
-                                .strong()
-                        );
-
-                        let response = ui.add(
-                            egui::TextEdit::singleline(&mut self.password_query)
-                                .password(true)
-                                .frame(false)
-                                .desired_width(200.0)
-                        );
-
-                        // Force focus
-                        response.request_focus();
-                        ui.label(egui::RichText::new(format!("for '{}'", self.pending_sudo_command)).italics());
-                    }
+                if self.output.is_some() {
+                    self.show_output(ui);
+                } else if self.core.mode == AppMode::Search {
+                    ui.style_mut().spacing.item_spacing = egui::Vec2::ZERO;
+                    clicked = self.show_results(ui);
                 }
-            });
+            } else {
+                ui.horizontal(|ui| {
+                    clicked = self.show_input_row(ui, true);
+                });
+            }
         });
 
+        // Grow/shrink with the result count or output panel (and fit the monitor), only when it changes
+        let size = egui::vec2(self.config.window_size(self.monitor_width)[0], self.desired_height());
+        if size != self.window_size {
+            self.window_size = size;
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+        }
+
+        // Alt+1..9 runs the Nth visible result, just like clicking it
+        if self.core.mode == AppMode::Search && clicked.is_none() {
+            clicked = quick_digit
+                .and_then(|digit| self.core.quick_pick(digit + 1, self.config.max_results))
+                .map(|i| (i, ResultClick::Run));
+        }
+
+        // Handle mouse click
+        if let Some((i, click)) = clicked {
+            should_close = self.click_result(i, click);
+        }
+
         // Handle Enter Key
-        if enter_pressed {
-            should_close = self.attempt_run();
+        if enter_pressed || attached_pressed {
+            should_close = self.attempt_run(attached_pressed);
         }
 
         if should_close {
             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result_names(app: &DeeMenu) -> Vec<&str> {
+        app.core.filtered_executables.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    fn app_with(names: &[&str]) -> DeeMenu {
+        let mut app = DeeMenu::from_config(Config::default(), None, egui::Context::default());
+        app.core.all_executables = names.iter().map(|name| Candidate::new(name.to_string())).collect();
+        app
+    }
+
+    #[test]
+    fn dmenu_choices_keep_their_order_and_skip_prefixes() {
+        let mut app = app_with(&[]);
+        app.use_choices(["zebra", "=1+1", "apple"].map(|line| Candidate::new(line.to_string())).to_vec());
+        assert_eq!(result_names(&app), ["zebra", "=1+1", "apple"]);
+
+        app.core.search_query = "=1".to_string();
+        app.core.update_filter();
+        assert_eq!(result_names(&app), ["=1+1"]);
+
+        assert!(app.attempt_run(false));
+        assert!(app.launched.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn long_names_are_cut_on_character_boundaries() {
+        assert_eq!(ellipsis_cut("short", 10), None);
+        assert_eq!(ellipsis_cut("exactly10!", 10), None);
+        assert_eq!(ellipsis_cut("much-too-long", 5), Some(4));
+        assert_eq!(ellipsis_cut("éééééé", 3), Some(4));
+        assert_eq!(ellipsis_cut("much-too-long", 0), None);
+    }
+
+    #[test]
+    fn unusable_fonts_fall_back_to_the_defaults() {
+        let mut config = Config::default();
+        assert!(custom_fonts(&config).is_none());
+
+        config.font = Some("/nonexistent/deemenu.ttf".to_string());
+        assert!(custom_fonts(&config).is_none());
+
+        let junk = env::temp_dir().join(format!("deemenu-font-test-{}.ttf", std::process::id()));
+        fs::write(&junk, "not a font").unwrap();
+        config.font = Some(junk.to_string_lossy().to_string());
+        assert!(custom_fonts(&config).is_none());
+        fs::remove_file(junk).unwrap();
+    }
+
+    #[test]
+    fn fallback_font_skips_missing_and_broken_files() {
+        let junk = env::temp_dir().join(format!("deemenu-fallback-test-{}.ttf", std::process::id()));
+        fs::write(&junk, "not a font").unwrap();
+
+        let mut fonts = egui::FontDefinitions::default();
+        assert!(!add_fallback_font(&mut fonts, &["/nonexistent/cjk.ttc", &junk.to_string_lossy()]));
+        assert!(!fonts.font_data.contains_key("fallback"));
+        fs::remove_file(junk).unwrap();
+    }
+
+    #[test]
+    fn reopening_drops_sudo_state_and_maybe_the_query() {
+        let mut app = app_with(&["htop", "nano"]);
+        app.core.search_query = "sudo nano".to_string();
+        app.attempt_run(false);
+        app.password_query.push_str("hunter2");
+        app.core.search_query = "nano".to_string();
+
+        app.config.clear_on_open = false;
+        app.reopen();
+        assert!(app.core.mode == AppMode::Search);
+        assert!(app.password_query.is_empty() && app.core.pending_sudo_command.is_empty());
+        assert_eq!(result_names(&app), ["nano"]);
+
+        app.config.clear_on_open = true;
+        app.core.toggle_sudo();
+        app.reopen();
+        assert_eq!(app.core.search_query, "");
+        assert_eq!(result_names(&app), ["htop", "nano"]);
+        assert!(!app.core.sudo_selection);
+    }
+
+    #[test]
+    fn strip_pills_reuse_laid_out_names() {
+        let mut app = app_with(&["htop", "kitty", "nano"]);
+        app.config.layout = Layout::Horizontal;
+        app.core.update_filter();
+
+        let ctx = app.ctx.clone();
+        let frame = |app: &DeeMenu| {
+            let _ = ctx.run(egui::RawInput::default(), |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| app.show_results(ui));
+            });
+            ctx.data_mut(|d| d.get_temp::<NameGalleys>(egui::Id::new("name_galleys"))).unwrap_or_default()
+        };
+        let first = frame(&app);
+        assert_eq!(first.len(), 3);
+        let again = frame(&app);
+        assert!(first.iter().all(|(key, galley)| Arc::ptr_eq(galley, &again[key])));
+
+        // Moving the highlight lays out only the two names whose look changed
+        app.core.select_next();
+        assert_eq!(frame(&app).len(), 5);
+    }
+
+    #[test]
+    fn empty_scan_warns_once_and_not_in_dmenu_mode() {
+        let mut app = app_with(&[]);
+        app.scan_rx = Some(mpsc::channel::<ScanEvent>().1);
+        app.drain_scan();
+        assert_eq!(app.error_message.as_deref(), Some("no programs found, check PATH"));
+        app.error_message = Some("failed to launch: htop".to_string());
+        app.drain_scan();
+        assert_eq!(app.error_message.as_deref(), Some("failed to launch: htop"));
+
+        let mut app = app_with(&[]);
+        app.use_choices(Vec::new());
+        app.scan_rx = Some(mpsc::channel::<ScanEvent>().1);
+        app.drain_scan();
+        assert_eq!(app.error_message, None);
+    }
+
+    #[test]
+    fn history_counts_a_launch_only_once_it_started() {
+        let mut app = app_with(&["htop", "nano"]);
+        app.config.history = false;
+        app.core.confirm.patterns = vec!["htop".to_string()];
+        app.core.update_filter();
+
+        // Esc at the confirmation: nothing started, and the next Enter starts over
+        assert!(!app.attempt_run(false));
+        assert_eq!(app.pending_history, Some((vec!["htop".to_string()], None)));
+        app.core.cancel_confirmation();
+
+        let _ = app.launch_tx.send(LaunchEvent::Failed("no such file".to_string()));
+        app.drain_launches();
+        assert!(app.pending_history.is_some());
+
+        let _ = app.launch_tx.send(LaunchEvent::Launched);
+        app.drain_launches();
+        assert!(app.pending_history.is_none());
+
+        // A command line typed with arguments waits the same way
+        app.core.set_query("htop -d 5".to_string());
+        assert!(!app.attempt_run(false));
+        assert_eq!(app.pending_history, Some((Vec::new(), Some("htop -d 5".to_string()))));
+    }
+
+    #[test]
+    fn context_menu_runs_the_clicked_result_with_sudo() {
+        let mut app = app_with(&["htop", "nano"]);
+        app.dry_run = true;
+        app.core.search_query = "o".to_string();
+        app.core.update_filter();
+
+        assert!(!app.click_result(1, ResultClick::Sudo));
+        assert!(app.core.mode == AppMode::SudoPassword);
+        assert_eq!(app.core.pending_sudo_command, "nano");
+    }
+
+    #[test]
+    fn password_box_takes_the_first_key_after_switching() {
+        let mut app = app_with(&["htop"]);
+        app.dry_run = true;
+        app.core.update_filter();
+        assert!(!app.click_result(0, ResultClick::Sudo));
+        assert!(app.focus_input);
+
+        let ctx = app.ctx.clone();
+        let input = egui::RawInput { events: vec![egui::Event::Text("h".to_string())], ..Default::default() };
+        let _ = ctx.run(input, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| app.show_password_box(ui));
+        });
+        assert_eq!(app.password_query.as_str(), "h");
+        assert!(!app.focus_input);
+    }
+
+    #[test]
+    fn printed_commands_carry_sudo_and_the_environment() {
+        let mut app = app_with(&[]);
+        let parts = ["sh", "-c", "ls | less"].map(String::from).to_vec();
+        assert_eq!(app.printed_command(parts.clone(), false), "sh -c 'ls | less'");
+        assert_eq!(app.printed_command(parts.clone(), true), "sudo sh -c 'ls | less'");
+
+        app.config.env.insert("MOZ_ENABLE_WAYLAND".to_string(), "1".to_string());
+        assert_eq!(app.printed_command(parts, false), "env MOZ_ENABLE_WAYLAND=1 sh -c 'ls | less'");
+    }
+
+    #[test]
+    fn no_matches_is_only_shown_for_unexplained_empty_results() {
+        let mut app = app_with(&["firefox"]);
+        assert!(!app.shows_no_matches());
+
+        for (query, expected) in [("fierfox", true), ("fire", false), ("=1+", false), ("example.org", false)] {
+            app.core.search_query = query.to_string();
+            app.core.update_filter();
+            assert_eq!(app.shows_no_matches(), expected, "{}", query);
+        }
+
+        // Still scanning: the spinner says so instead
+        let (_tx, rx) = mpsc::channel();
+        app.scan_rx = Some(rx);
+        app.core.search_query = "fierfox".to_string();
+        app.core.update_filter();
+        assert!(app.is_scanning() && !app.shows_no_matches());
+    }
+
+    #[test]
+    fn unknown_single_word_is_not_launched() {
+        let mut app = app_with(&["firefox"]);
+        app.core.search_query = "fierfox".to_string();
+        app.core.update_filter();
+
+        assert!(!app.attempt_run(false));
+        assert_eq!(app.launching, 0);
+        assert_eq!(app.error_message.as_deref(), Some("command not found: fierfox"));
+    }
+}
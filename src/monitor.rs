@@ -0,0 +1,85 @@
+use crate::config::MonitorChoice;
+use eframe::egui;
+use std::env;
+use std::process::Command;
+
+/// A monitor's area on the virtual desktop, as reported by `xrandr --listmonitors`
+#[derive(Clone, Copy)]
+pub struct Monitor {
+    pub rect: egui::Rect,
+    pub primary: bool,
+}
+
+/// Picks the monitor DeeMenu should appear on. Returns `None` when the
+/// monitor layout can't be queried (no xrandr, Wayland, ...).
+pub fn resolve(choice: MonitorChoice) -> Option<Monitor> {
+    let monitors = list();
+    let primary = monitors.iter().find(|m| m.primary).or(monitors.first()).copied();
+
+    match choice {
+        MonitorChoice::Primary => primary,
+        MonitorChoice::Index(i) => monitors.get(i).copied().or(primary),
+        MonitorChoice::Cursor => cursor_position()
+            .and_then(|pos| monitors.iter().find(|m| m.rect.contains(pos)).copied())
+            .or(primary),
+    }
+}
+
+/// Whether there is an X11 or Wayland display to open a window on at all.
+pub fn has_display() -> bool {
+    ["DISPLAY", "WAYLAND_DISPLAY"].iter().any(|var| env::var_os(var).is_some_and(|v| !v.is_empty()))
+}
+
+/// Whether the session is Wayland, where windows can't place themselves.
+pub fn is_wayland() -> bool {
+    env::var("XDG_SESSION_TYPE").is_ok_and(|t| t == "wayland")
+        || env::var_os("WAYLAND_DISPLAY").is_some_and(|d| !d.is_empty())
+}
+
+/// Whether position commands will be honored: always on X11, and on
+/// Wayland only when running through XWayland.
+pub fn can_position(xwayland: bool) -> bool {
+    xwayland || !is_wayland()
+}
+
+fn list() -> Vec<Monitor> {
+    let Ok(output) = Command::new("xrandr").arg("--listmonitors").output() else {
+        return Vec::new();
+    };
+
+    // " 0: +*DP-1 2560/597x1440/336+0+0  DP-1"
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            let name = fields.next()?;
+            let geometry = fields.next()?;
+
+            let (size, offset) = geometry.split_once('+')?;
+            let (x, y) = offset.split_once('+')?;
+            let (w, h) = size.split_once('x')?;
+            let w = w.split('/').next()?;
+            let h = h.split('/').next()?;
+
+            let min = egui::pos2(x.parse().ok()?, y.parse().ok()?);
+            let size = egui::vec2(w.parse().ok()?, h.parse().ok()?);
+
+            Some(Monitor {
+                rect: egui::Rect::from_min_size(min, size),
+                primary: name.contains('*'),
+            })
+        })
+        .collect()
+}
+
+fn cursor_position() -> Option<egui::Pos2> {
+    let output = Command::new("xdotool").args(["getmouselocation", "--shell"]).output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    let value = |key: &str| -> Option<f32> {
+        stdout.lines().find_map(|line| line.strip_prefix(key)?.strip_prefix('=')?.parse().ok())
+    };
+
+    Some(egui::pos2(value("X")?, value("Y")?))
+}
@@ -0,0 +1,126 @@
+use eframe::egui;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// Lines kept for the output panel; older ones scroll away
+const MAX_LINES: usize = 1000;
+
+enum OutputEvent {
+    Line(String),
+    Exited(String),
+}
+
+/// A command started with Shift+Enter, whose stdout/stderr stream into the window.
+pub struct CommandOutput {
+    pub command: String,
+    pub lines: Vec<String>,
+    /// How the command ended, once it has
+    pub status: Option<String>,
+    rx: Receiver<OutputEvent>,
+}
+
+impl CommandOutput {
+    /// Starts `parts` with both output streams piped back to us.
+    pub fn start(
+        command: &str,
+        parts: &[String],
+        env_vars: &HashMap<String, String>,
+        cwd: Option<&OsString>,
+        ctx: &egui::Context,
+    ) -> std::io::Result<Self> {
+        let (program, args) = parts.split_first().ok_or(std::io::ErrorKind::InvalidInput)?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(args)
+            .envs(env_vars)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0);
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        let mut child = cmd.spawn()?;
+
+        let (tx, rx) = mpsc::channel();
+        let readers = [
+            child.stdout.take().map(|out| forward_lines(out, tx.clone(), ctx.clone())),
+            child.stderr.take().map(|err| forward_lines(err, tx.clone(), ctx.clone())),
+        ];
+
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let status = match child.wait() {
+                Ok(status) => match status.code() {
+                    Some(0) => "finished".to_string(),
+                    Some(code) => format!("exited with status {}", code),
+                    None => "killed by a signal".to_string(),
+                },
+                Err(e) => format!("lost track of the process: {}", e),
+            };
+
+            // Report the exit after the last line
+            for reader in readers.into_iter().flatten() {
+                let _ = reader.join();
+            }
+            let _ = tx.send(OutputEvent::Exited(status));
+            ctx.request_repaint();
+        });
+
+        Ok(Self { command: command.to_string(), lines: Vec::new(), status: None, rx })
+    }
+
+    /// Takes in whatever the command printed since the last frame.
+    pub fn drain(&mut self) {
+        for event in self.rx.try_iter() {
+            match event {
+                OutputEvent::Line(line) => self.lines.push(line),
+                OutputEvent::Exited(status) => self.status = Some(status),
+            }
+        }
+
+        let excess = self.lines.len().saturating_sub(MAX_LINES);
+        self.lines.drain(..excess);
+    }
+}
+
+fn forward_lines(stream: impl Read + Send + 'static, tx: Sender<OutputEvent>, ctx: egui::Context) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        // Not `lines()`: a stray invalid UTF-8 byte shouldn't end the stream
+        let mut reader = BufReader::new(stream);
+        let mut buf = Vec::new();
+        while reader.read_until(b'\n', &mut buf).is_ok_and(|read| read > 0) {
+            let line = String::from_utf8_lossy(&buf).trim_end_matches(['\r', '\n']).to_string();
+            buf.clear();
+            if tx.send(OutputEvent::Line(line)).is_err() { break; }
+            ctx.request_repaint();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn collects_both_streams_and_the_exit_status() {
+        let parts = ["sh", "-c", "echo out; echo err >&2; exit 3"].map(String::from);
+        let mut output = CommandOutput::start("demo", &parts, &HashMap::new(), None, &egui::Context::default()).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while output.status.is_none() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+            output.drain();
+        }
+
+        output.lines.sort();
+        assert_eq!(output.lines, ["err", "out"]);
+        assert_eq!(output.status.as_deref(), Some("exited with status 3"));
+    }
+}
@@ -0,0 +1,480 @@
+use crate::core::Candidate;
+use crate::{calc, emoji, files, procs, shell, web};
+use std::collections::HashMap;
+
+/// What to list for a query.
+pub enum Results {
+    /// Search the regular candidates for this text (e.g. what follows `sudo `)
+    Filter(String),
+    /// The prefix's own entries, e.g. the calculator result
+    Entries(Vec<Candidate>),
+}
+
+/// What Enter does; carried out by `DeeMenu::perform`.
+#[derive(Debug)]
+pub enum Action {
+    Nothing,
+    /// Copy the text to the clipboard and close
+    Copy(String),
+    /// Replace the search text, e.g. to step into a directory
+    SetQuery(String),
+    /// Ask for the password, then run the command with sudo
+    Sudo(String),
+    /// Stop the process with this PID (SIGTERM, or SIGKILL with Shift+Enter)
+    Signal(u32),
+    Launch { command: String, in_terminal: bool },
+    /// Open the config file in the default text editor
+    EditConfig,
+    /// Re-read the config and scan again
+    Reload,
+    /// Several launches at once: the entries marked with Ctrl+Space
+    Batch(Vec<Action>),
+    Error(String),
+}
+
+/// A special mode picked by how the query starts (`sudo `, `=`, `?`, ...).
+/// Queries no prefix matches are filtered and launched as plain commands.
+pub trait Prefix {
+    /// Whether this prefix handles the (trimmed) query
+    fn matches(&self, query: &str) -> bool;
+
+    fn results(&self, query: &str) -> Results;
+
+    /// `selected` is the highlighted result, if there is any
+    fn run(&self, query: &str, selected: Option<&Candidate>) -> Action;
+}
+
+/// The built-in prefixes, in the order they are consulted.
+pub fn registry(search_url: &str) -> Vec<Box<dyn Prefix>> {
+    vec![
+        Box::new(Builtins),
+        Box::new(Calc),
+        Box::new(Emoji),
+        Box::new(Web { search_url: search_url.to_string() }),
+        Box::new(Paths),
+        Box::new(Sudo),
+        Box::new(Kill),
+        Box::new(Terminal),
+        Box::new(Shell),
+    ]
+}
+
+/// The command Enter runs for typed text: the highlighted entry, unless the
+/// user typed arguments. Desktop entry names may contain spaces, so an exact
+/// name still picks the entry. Returns the command and whether the entry wants a terminal.
+pub fn chosen_command(typed: &str, selected: Option<&Candidate>) -> (String, bool) {
+    match selected {
+        Some(candidate) if !typed.contains(' ') || typed == candidate.name => {
+            (candidate.command().to_string(), candidate.terminal)
+        }
+        _ => (typed.to_string(), false),
+    }
+}
+
+/// Replaces a leading alias with its command line, keeping any typed arguments.
+pub fn expand_alias(command: &str, aliases: &HashMap<String, String>) -> String {
+    let (head, args) = command.split_once(' ').unwrap_or((command, ""));
+    match aliases.get(head) {
+        Some(expansion) if args.is_empty() => expansion.clone(),
+        Some(expansion) => format!("{} {}", expansion, args),
+        None => command.to_string(),
+    }
+}
+
+/// `str::strip_prefix`, but matching an ASCII prefix like `sudo ` in any case.
+pub fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    text.get(..prefix.len())
+        .filter(|head| head.eq_ignore_ascii_case(prefix))
+        .map(|_| &text[prefix.len()..])
+}
+
+/// What follows a leading command word like `sudo`, in any case and after any
+/// amount of whitespace: `SUDO   nano` gives `nano`. `sudoedit` is not `sudo`.
+pub fn strip_command_word<'a>(text: &'a str, word: &str) -> Option<&'a str> {
+    let rest = strip_prefix_ignore_case(text.trim_start(), word)?;
+    rest.starts_with(char::is_whitespace).then(|| rest.trim_start())
+}
+
+/// The query after `prefix`, for prefixes that have already matched.
+fn rest<'a>(query: &'a str, prefix: &str) -> &'a str {
+    strip_prefix_ignore_case(query, prefix).unwrap_or_default().trim()
+}
+
+/// DeeMenu's own commands, listed once `deemenu:` is typed
+const BUILTINS: [&str; 2] = ["deemenu:config", "deemenu:reload"];
+
+/// `deemenu:config` opens the config file, `deemenu:reload` applies it without restarting.
+struct Builtins;
+
+impl Prefix for Builtins {
+    fn matches(&self, query: &str) -> bool {
+        query.starts_with("deemenu:")
+    }
+
+    fn results(&self, query: &str) -> Results {
+        let commands = BUILTINS.iter().filter(|command| command.starts_with(query));
+        Results::Entries(commands.map(|command| Candidate::new(command.to_string())).collect())
+    }
+
+    fn run(&self, query: &str, selected: Option<&Candidate>) -> Action {
+        match selected.map_or(query, |command| command.name.as_str()) {
+            "deemenu:config" => Action::EditConfig,
+            "deemenu:reload" => Action::Reload,
+            other => Action::Error(format!("unknown command: {}", other)),
+        }
+    }
+}
+
+/// `=3*(4+5)` or `=10 mi to km`: the only result is the evaluated expression
+/// (with its unit); Enter copies the number.
+struct Calc;
+
+impl Prefix for Calc {
+    fn matches(&self, query: &str) -> bool {
+        query.starts_with('=')
+    }
+
+    fn results(&self, query: &str) -> Results {
+        let value = calc::answer(query.strip_prefix('=').unwrap_or_default());
+        Results::Entries(value.map(Candidate::new).into_iter().collect())
+    }
+
+    fn run(&self, _query: &str, selected: Option<&Candidate>) -> Action {
+        let number = selected.and_then(|result| result.name.split(' ').next());
+        number.map_or(Action::Nothing, |number| Action::Copy(number.to_string()))
+    }
+}
+
+/// `:heart` lists matching emoji and symbols as `<character> <name>`; Enter copies the character.
+struct Emoji;
+
+impl Prefix for Emoji {
+    fn matches(&self, query: &str) -> bool {
+        query.starts_with(':')
+    }
+
+    fn results(&self, query: &str) -> Results {
+        let found = emoji::search(&query[1..]);
+        Results::Entries(found.into_iter().map(|(character, name)| Candidate::new(format!("{} {}", character, name))).collect())
+    }
+
+    fn run(&self, _query: &str, selected: Option<&Candidate>) -> Action {
+        let character = selected.and_then(|result| result.name.split(' ').next());
+        character.map_or(Action::Nothing, |character| Action::Copy(character.to_string()))
+    }
+}
+
+/// `?terms` searches the web; a typed URL (or a bare domain nothing local matches) opens directly.
+struct Web {
+    search_url: String,
+}
+
+impl Prefix for Web {
+    fn matches(&self, query: &str) -> bool {
+        query.starts_with('?') || web::as_url(query).is_some()
+    }
+
+    fn results(&self, query: &str) -> Results {
+        if query.starts_with('?') {
+            Results::Entries(Vec::new())
+        } else {
+            Results::Filter(query.to_string())
+        }
+    }
+
+    fn run(&self, query: &str, selected: Option<&Candidate>) -> Action {
+        let url = match query.strip_prefix('?') {
+            Some(terms) if terms.trim().is_empty() => return Action::Nothing,
+            Some(terms) => web::search_url(&self.search_url, terms.trim()),
+            // A bare domain only opens when it matches nothing local
+            None if selected.is_some() && !query.contains("://") => {
+                let (command, in_terminal) = chosen_command(query, selected);
+                return Action::Launch { command, in_terminal };
+            }
+            None => web::as_url(query).unwrap_or_default(),
+        };
+        Action::Launch { command: format!("xdg-open {}", shell::quote(&url)), in_terminal: false }
+    }
+}
+
+/// `/` and `~/` complete paths: Enter steps into directories, opens files and runs executables.
+struct Paths;
+
+impl Prefix for Paths {
+    fn matches(&self, query: &str) -> bool {
+        files::is_path_query(query)
+    }
+
+    fn results(&self, query: &str) -> Results {
+        Results::Entries(files::complete(query).into_iter().map(Candidate::new).collect())
+    }
+
+    fn run(&self, query: &str, selected: Option<&Candidate>) -> Action {
+        // A path with arguments is a command line, but spaces can be part of the path itself
+        let target = selected.map_or(query, |c| c.name.as_str());
+        if query.contains(' ') && !files::expand_tilde(target).exists() {
+            return Action::Launch { command: query.to_string(), in_terminal: false };
+        }
+
+        if target.ends_with('/') {
+            return Action::SetQuery(target.to_string());
+        }
+
+        let path = files::expand_tilde(target);
+        if !path.exists() {
+            return Action::Error(format!("no such file: {}", target));
+        }
+
+        let opener = if files::is_executable(&path) { "" } else { "xdg-open " };
+        let command = format!("{}{}", opener, shell::quote(&path.to_string_lossy()));
+        Action::Launch { command, in_terminal: false }
+    }
+}
+
+/// `sudo <command>` asks for the password and runs the typed command as root.
+struct Sudo;
+
+impl Prefix for Sudo {
+    fn matches(&self, query: &str) -> bool {
+        strip_command_word(query, "sudo").is_some()
+    }
+
+    fn results(&self, query: &str) -> Results {
+        Results::Filter(strip_command_word(query, "sudo").unwrap_or_default().trim_end().to_string())
+    }
+
+    fn run(&self, query: &str, _selected: Option<&Candidate>) -> Action {
+        match strip_command_word(query, "sudo").unwrap_or_default().trim_end() {
+            "" => Action::Nothing,
+            command => Action::Sudo(command.to_string()),
+        }
+    }
+}
+
+/// `kill firefox` lists running processes as `<pid> <name>`; Enter stops the highlighted one.
+/// A typed command line (`kill -9 1234`, `kill 12 34`) is left to run as it is.
+struct Kill;
+
+impl Prefix for Kill {
+    fn matches(&self, query: &str) -> bool {
+        strip_command_word(query, "kill").is_some_and(|wanted| {
+            let wanted = wanted.trim_end();
+            !wanted.starts_with('-') && !wanted.contains(char::is_whitespace)
+        })
+    }
+
+    fn results(&self, query: &str) -> Results {
+        let wanted = strip_command_word(query, "kill").unwrap_or_default().trim_end().to_lowercase();
+        let me = std::process::id();
+
+        let processes = procs::list()
+            .into_iter()
+            .filter(|p| p.pid != me)
+            .filter(|p| p.name.to_lowercase().contains(&wanted) || p.pid.to_string().starts_with(&wanted))
+            .map(|p| Candidate::new(format!("{} {}", p.pid, p.name)))
+            .collect();
+        Results::Entries(processes)
+    }
+
+    fn run(&self, query: &str, selected: Option<&Candidate>) -> Action {
+        let pid = selected.and_then(|p| p.name.split(' ').next()?.parse().ok());
+        match pid {
+            Some(pid) => Action::Signal(pid),
+            // Nothing listed, e.g. a pid the process list doesn't show: let kill itself try
+            None if strip_command_word(query, "kill").is_some_and(|wanted| !wanted.trim_end().is_empty()) => {
+                Action::Launch { command: query.trim().to_string(), in_terminal: false }
+            }
+            None => Action::Nothing,
+        }
+    }
+}
+
+/// `term: htop` runs the command in a terminal emulator.
+struct Terminal;
+
+impl Prefix for Terminal {
+    fn matches(&self, query: &str) -> bool {
+        strip_prefix_ignore_case(query, "term:").is_some()
+    }
+
+    fn results(&self, query: &str) -> Results {
+        Results::Filter(rest(query, "term:").to_string())
+    }
+
+    fn run(&self, query: &str, selected: Option<&Candidate>) -> Action {
+        let (command, _) = chosen_command(rest(query, "term:"), selected);
+        Action::Launch { command, in_terminal: true }
+    }
+}
+
+/// `$ cmd` or `!cmd` runs the command line through `$SHELL -c`, even when
+/// nothing in it looks like shell syntax (e.g. to use shell aliases or builtins).
+struct Shell;
+
+impl Shell {
+    fn command(query: &str) -> Option<&str> {
+        query.strip_prefix('!').map(str::trim).or_else(|| strip_command_word(query, "$"))
+    }
+}
+
+impl Prefix for Shell {
+    fn matches(&self, query: &str) -> bool {
+        Self::command(query).is_some()
+    }
+
+    fn results(&self, query: &str) -> Results {
+        Results::Filter(Self::command(query).unwrap_or_default().to_string())
+    }
+
+    fn run(&self, query: &str, _selected: Option<&Candidate>) -> Action {
+        match Self::command(query).unwrap_or_default() {
+            "" => Action::Nothing,
+            command => Action::Launch {
+                command: format!("{} -c {}", shell::quote(&shell::user_shell()), shell::quote(command)),
+                in_terminal: false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn find<'a>(prefixes: &'a [Box<dyn Prefix>], query: &str) -> Option<&'a dyn Prefix> {
+        prefixes.iter().find(|p| p.matches(query)).map(|p| p.as_ref())
+    }
+
+    #[test]
+    fn sudo_filters_and_runs_the_rest_of_the_query() {
+        let prefixes = registry("https://example.com/?q={query}");
+        let sudo = find(&prefixes, "SUDO  htop").unwrap();
+
+        assert!(matches!(sudo.results("SUDO  htop"), Results::Filter(text) if text == "htop"));
+        assert!(matches!(sudo.run("SUDO  htop", None), Action::Sudo(cmd) if cmd == "htop"));
+        assert!(matches!(sudo.run("sudo ", None), Action::Nothing));
+    }
+
+    #[test]
+    fn builtins_complete_and_run() {
+        let prefixes = registry("https://example.com/?q={query}");
+        let builtins = find(&prefixes, "deemenu:re").unwrap();
+
+        let Results::Entries(found) = builtins.results("deemenu:re") else { panic!("not listed") };
+        assert_eq!(found.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["deemenu:reload"]);
+        assert!(matches!(builtins.run("deemenu:re", found.first()), Action::Reload));
+        assert!(matches!(builtins.run("deemenu:config", None), Action::EditConfig));
+        assert!(matches!(builtins.run("deemenu:nope", None), Action::Error(_)));
+    }
+
+    #[test]
+    fn command_words_allow_any_case_and_spacing() {
+        assert_eq!(strip_command_word("  sudo apt update", "sudo"), Some("apt update"));
+        assert_eq!(strip_command_word("SUDO reboot", "sudo"), Some("reboot"));
+        assert_eq!(strip_command_word("sudo\t  nano", "sudo"), Some("nano"));
+        assert_eq!(strip_command_word("sudoedit x", "sudo"), None);
+        assert_eq!(strip_command_word("sudo", "sudo"), None);
+    }
+
+    #[test]
+    fn plain_queries_match_no_prefix() {
+        let prefixes = registry("https://example.com/?q={query}");
+        assert!(find(&prefixes, "firefox").is_none());
+        assert!(find(&prefixes, "pseudo x").is_none());
+    }
+
+    #[test]
+    fn terminal_prefix_runs_the_selection_in_a_terminal() {
+        let prefixes = registry("https://example.com/?q={query}");
+        let htop = Candidate::new("htop".to_string());
+        let action = find(&prefixes, "term: ht").unwrap().run("term: ht", Some(&htop));
+
+        assert!(matches!(action, Action::Launch { command, in_terminal: true } if command == "htop"));
+    }
+
+    #[test]
+    fn emoji_prefix_copies_the_character() {
+        let prefixes = registry("https://example.com/?q={query}");
+        let emoji = find(&prefixes, ":rocket").unwrap();
+
+        let Results::Entries(found) = emoji.results(":rocket") else { panic!("expected entries") };
+        assert_eq!(found.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["🚀 rocket"]);
+        assert!(matches!(emoji.run(":rocket", found.first()), Action::Copy(text) if text == "🚀"));
+        assert!(matches!(emoji.run(":nothing", None), Action::Nothing));
+    }
+
+    #[test]
+    fn paths_with_spaces_open_rather_than_run() {
+        let dir = std::env::temp_dir().join(format!("deemenu-paths-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("My Documents")).unwrap();
+        fs::write(dir.join("My Documents").join("notes.txt"), "").unwrap();
+
+        let prefixes = registry("https://example.com/?q={query}");
+        let query = format!("{}/My Documents/no", dir.display());
+        let paths = find(&prefixes, &query).unwrap();
+        let Results::Entries(entries) = paths.results(&query) else { panic!("paths list entries") };
+        assert_eq!(entries.len(), 1);
+
+        let opened = format!("xdg-open '{}/My Documents/notes.txt'", dir.display());
+        assert!(matches!(paths.run(&query, entries.first()), Action::Launch { command, .. } if command == opened));
+        let into = format!("{}/My Documents/", dir.display());
+        assert!(matches!(paths.run(&format!("{}/My Doc", dir.display()), Some(&Candidate::new(into.clone()))), Action::SetQuery(q) if q == into));
+        // Arguments after an executable still make a command line
+        assert!(matches!(paths.run("/bin/sh -c true", None), Action::Launch { command, .. } if command == "/bin/sh -c true"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn paths_list_every_match() {
+        let dir = std::env::temp_dir().join(format!("deemenu-many-paths-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..80 {
+            fs::write(dir.join(format!("file{:02}", i)), "").unwrap();
+        }
+
+        let prefixes = registry("https://example.com/?q={query}");
+        let query = format!("{}/file", dir.display());
+        let Results::Entries(entries) = find(&prefixes, &query).unwrap().results(&query) else { panic!("paths list entries") };
+        assert_eq!(entries.len(), 80);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn kill_signals_the_highlighted_pid() {
+        let prefixes = registry("https://example.com/?q={query}");
+        let kill = find(&prefixes, "kill fire").unwrap();
+        let process = Candidate::new("4242 firefox".to_string());
+
+        assert!(matches!(kill.run("kill fire", Some(&process)), Action::Signal(4242)));
+        assert!(matches!(kill.run("kill 4242", None), Action::Launch { command, .. } if command == "kill 4242"));
+        assert!(matches!(kill.run("kill ", None), Action::Nothing));
+
+        // Command lines with options or several pids aren't for the picker
+        assert!(find(&prefixes, "kill -9 1234").is_none());
+        assert!(find(&prefixes, "kill 12 34").is_none());
+        assert!(find(&prefixes, "kill ").is_some());
+    }
+
+    #[test]
+    fn shell_prefix_wraps_the_command_line() {
+        let prefixes = registry("https://example.com/?q={query}");
+        let action = find(&prefixes, "!ll").unwrap().run("!ll", None);
+
+        let Action::Launch { command, .. } = action else { panic!("expected a launch") };
+        assert_eq!(shell::split(&command).unwrap()[1..], ["-c", "ll"]);
+        assert!(find(&prefixes, "$ ll").is_some());
+        assert!(find(&prefixes, "$EDITOR notes.txt").is_none());
+    }
+
+    #[test]
+    fn aliases_expand_with_their_arguments() {
+        let aliases = HashMap::from([("ff".to_string(), "firefox --private-window".to_string())]);
+
+        assert_eq!(expand_alias("ff", &aliases), "firefox --private-window");
+        assert_eq!(expand_alias("ff example.com", &aliases), "firefox --private-window example.com");
+        assert_eq!(expand_alias("ffmpeg -i x", &aliases), "ffmpeg -i x");
+    }
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::process::Command;
+
+/// A running process, as listed by the `kill ` prefix.
+pub struct Process {
+    pub pid: u32,
+    /// The short command name from `/proc/<pid>/comm`
+    pub name: String,
+}
+
+/// Every process we can see in `/proc`, by name.
+pub fn list() -> Vec<Process> {
+    let Ok(entries) = fs::read_dir("/proc") else { return Vec::new() };
+
+    let mut processes: Vec<Process> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            // Processes can exit between listing and reading
+            let name = fs::read_to_string(entry.path().join("comm")).ok()?;
+            Some(Process { pid, name: name.trim_end().to_string() })
+        })
+        .collect();
+
+    processes.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()).then(a.pid.cmp(&b.pid)));
+    processes
+}
+
+/// Sends `signal` (`TERM`, `KILL`, ...) through `kill(1)`, returning its complaint on failure.
+pub fn signal(pid: u32, signal: &str) -> Result<(), String> {
+    let output = Command::new("kill")
+        .arg(format!("-{}", signal))
+        .arg(pid.to_string())
+        .output()
+        .map_err(|e| format!("can't run kill: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_this_process() {
+        let me = list().into_iter().find(|p| p.pid == std::process::id()).unwrap();
+        assert!(!me.name.is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+/// Splits a command line into arguments the way a POSIX shell would,
+/// honoring 'single quotes', "double quotes" and backslash escapes.
+/// Returns `None` if a quote is left open or the line ends in a lone backslash.
+pub fn split(input: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    // Tracks whether a word has started, so `""` still produces an (empty) argument
+    let mut in_word = false;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    args.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            '\\' => {
+                current.push(chars.next()?);
+                in_word = true;
+            }
+            '\'' => {
+                // Everything up to the closing quote is literal
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => current.push(c),
+                    }
+                }
+                in_word = true;
+            }
+            '"' => {
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        // Inside double quotes a backslash only escapes these
+                        '\\' => match chars.next()? {
+                            c @ ('"' | '\\' | '$' | '`') => current.push(c),
+                            '\n' => {}
+                            c => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                        },
+                        c => current.push(c),
+                    }
+                }
+                in_word = true;
+            }
+            c => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if in_word {
+        args.push(current);
+    }
+
+    Some(args)
+}
+
+/// Whether running `input` takes a real shell: it has pipes, redirections,
+/// `&&`/`;`, globs, `$` expansions or a leading `~` outside single quotes.
+pub fn needs_shell(input: &str) -> bool {
+    let mut chars = input.chars();
+    let mut word_start = true;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '|' | '&' | ';' | '<' | '>' | '(' | ')' | '$' | '`' | '*' | '?' | '[' | '{' => return true,
+            '~' if word_start => return true,
+            '\\' => {
+                chars.next();
+            }
+            // Single quotes are literal; an unclosed one is `split`'s error to report
+            '\'' if !chars.by_ref().any(|c| c == '\'') => return false,
+            // Double quotes still expand `$` and backticks
+            '"' => loop {
+                match chars.next() {
+                    None => return false,
+                    Some('"') => break,
+                    Some('$' | '`') => return true,
+                    Some('\\') => {
+                        chars.next();
+                    }
+                    Some(_) => {}
+                }
+            },
+            _ => {}
+        }
+        word_start = c.is_whitespace();
+    }
+    false
+}
+
+/// The program and arguments for a command line: split and run directly when
+/// that's enough, otherwise handed to `$SHELL -c` (see `needs_shell`).
+/// `None` if a quote is left open.
+pub fn command_parts(command: &str) -> Option<Vec<String>> {
+    if needs_shell(command) {
+        Some(vec![user_shell(), "-c".to_string(), command.to_string()])
+    } else {
+        split(command)
+    }
+}
+
+/// `$SHELL`, or `/bin/sh` without one.
+pub fn user_shell() -> String {
+    std::env::var("SHELL").ok().filter(|shell| !shell.is_empty()).unwrap_or_else(|| "/bin/sh".to_string())
+}
+
+/// Quotes a single argument so `split` gives it back unchanged.
+pub fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// A command line for `parts`, quoting only the arguments that need it.
+pub fn join(parts: &[String]) -> String {
+    let plain = |arg: &str| !arg.is_empty() && arg.chars().all(|c| c.is_alphanumeric() || "_-./:=@%+,".contains(c));
+    parts.iter()
+        .map(|arg| if plain(arg) { arg.clone() } else { quote(arg) })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_quotes_only_where_needed() {
+        let parts = ["kitty", "-e", "sh", "-c", "ls | less", "", "it's"].map(String::from);
+        let line = join(&parts);
+        assert_eq!(line, r"kitty -e sh -c 'ls | less' '' 'it'\''s'");
+        assert_eq!(split(&line).unwrap(), parts);
+    }
+
+    #[test]
+    fn double_quotes_keep_spaces() {
+        assert_eq!(split(r#"echo "a b""#).unwrap(), ["echo", "a b"]);
+        assert_eq!(split(r#"mpv "My Movie.mkv" --loop"#).unwrap(), ["mpv", "My Movie.mkv", "--loop"]);
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        assert_eq!(split("cmd 'single quoted'").unwrap(), ["cmd", "single quoted"]);
+        assert_eq!(split(r"cmd 'no \escapes'").unwrap(), ["cmd", r"no \escapes"]);
+    }
+
+    #[test]
+    fn backslash_escapes_spaces() {
+        assert_eq!(split(r"ls My\ Documents").unwrap(), ["ls", "My Documents"]);
+        assert_eq!(split(r#"echo "say \"hi\"""#).unwrap(), ["echo", r#"say "hi""#]);
+    }
+
+    #[test]
+    fn quotes_join_adjacent_text() {
+        assert_eq!(split(r#"--title="a b"c"#).unwrap(), ["--title=a bc"]);
+        assert_eq!(split(r#"cmd """#).unwrap(), ["cmd", ""]);
+    }
+
+    #[test]
+    fn quote_round_trips() {
+        for arg in ["plain", "with space", "it's", "https://x.org/?a=1&b='2'"] {
+            assert_eq!(split(&quote(arg)).unwrap(), [arg]);
+        }
+    }
+
+    #[test]
+    fn unterminated_input_is_rejected() {
+        assert_eq!(split(r#"echo "oops"#), None);
+        assert_eq!(split("echo 'oops"), None);
+        assert_eq!(split(r"echo oops\"), None);
+    }
+
+    #[test]
+    fn detects_shell_syntax_outside_quotes() {
+        for command in ["grep foo *.txt | less", "make && make install", "echo $HOME", "ls ~/src", r#"echo "$USER""#] {
+            assert!(needs_shell(command), "{}", command);
+        }
+        for command in ["firefox --private-window", "xdg-open 'https://x.org/?a=1&b=2'", r"echo a\|b", "git log --format=%h~"] {
+            assert!(!needs_shell(command), "{}", command);
+        }
+    }
+
+    #[test]
+    fn shell_commands_are_not_split() {
+        assert_eq!(command_parts("htop -d 5").unwrap(), ["htop", "-d", "5"]);
+        assert_eq!(command_parts("ls | wc -l").unwrap()[1..], ["-c", "ls | wc -l"]);
+    }
+}
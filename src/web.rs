@@ -0,0 +1,67 @@
+/// Builds a search URL from a template containing `{query}`.
+pub fn search_url(template: &str, terms: &str) -> String {
+    template.replace("{query}", &encode(terms))
+}
+
+/// If the input looks like a URL or a bare domain (`github.com/foo`),
+/// returns it as something a browser can open.
+pub fn as_url(input: &str) -> Option<String> {
+    if input.contains(char::is_whitespace) { return None; }
+
+    if input.starts_with("http://") || input.starts_with("https://") {
+        return Some(input.to_string());
+    }
+
+    // Bare domain: dotted host with an alphabetic TLD, optionally followed by a path
+    let host = input.split(['/', '?', '#']).next()?;
+    let host = host.split(':').next()?; // drop a :port
+    let (name, tld) = host.rsplit_once('.')?;
+    let valid_label = |label: &str| {
+        !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+
+    let is_domain = name.split('.').all(valid_label)
+        && tld.len() >= 2
+        && tld.chars().all(|c| c.is_ascii_alphabetic());
+
+    is_domain.then(|| format!("https://{}", input))
+}
+
+/// Percent-encodes everything except RFC 3986 unreserved characters.
+fn encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_search_terms_into_template() {
+        assert_eq!(
+            search_url("https://duckduckgo.com/?q={query}", "rust & egui"),
+            "https://duckduckgo.com/?q=rust%20%26%20egui"
+        );
+    }
+
+    #[test]
+    fn recognizes_urls_and_domains() {
+        assert_eq!(as_url("github.com").as_deref(), Some("https://github.com"));
+        assert_eq!(as_url("docs.rs/egui").as_deref(), Some("https://docs.rs/egui"));
+        assert_eq!(as_url("http://localhost:8080").as_deref(), Some("http://localhost:8080"));
+    }
+
+    #[test]
+    fn ignores_commands_and_versioned_binaries() {
+        assert_eq!(as_url("python3.11"), None);
+        assert_eq!(as_url("firefox"), None);
+        assert_eq!(as_url("echo a.b"), None);
+    }
+}